@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+pub const CONFIG_FILE_NAME: &str = "refactorign.toml";
+
+// defaults for the command line options, shared through a file
+// every setting is optional, and the options given on the command line win
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub path: Option<Vec<String>>,
+    pub paths_from: Option<String>,
+    pub jobs: Option<usize>,
+    pub destination: Option<String>,
+    pub output: Option<String>, // name of the refactored file
+    pub level: Option<isize>,
+    pub strict: Option<bool>,
+    pub prune_dead: Option<bool>,
+    pub history: Option<String>,
+    pub protect: Option<Vec<String>>,
+    pub overwrite: Option<bool>,
+    pub backup: Option<bool>,
+    pub report: Option<bool>,
+    pub verbose: Option<isize>,
+    pub validate: Option<bool>,
+    pub merge: MergeConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MergeConfig {
+    pub max_combinations: Option<usize>, // sets of lines tried at most for each size
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+    // paths in the file are relative to the directory of the file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut config = Self::parse(&text)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let resolve = |p: &String| dir.join(p).to_string_lossy().to_string();
+        config.path = config.path.map(|paths| paths.iter().map(resolve).collect());
+        config.paths_from = config.paths_from.as_ref().map(resolve);
+        config.destination = config.destination.as_ref().map(resolve);
+        config.history = config.history.as_ref().map(resolve);
+        Ok(config)
+    }
+}
+
+// the configuration file next to the .gitignore file, or else at the root of its repository
+pub fn find(gitignore: &Path) -> Option<PathBuf> {
+    let dir = gitignore.parent().unwrap_or(Path::new(""));
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let dir = dir.canonicalize().unwrap_or(dir.to_path_buf());
+    dir.ancestors()
+        .take(1)
+        .chain(dir.ancestors().find(|d| d.join(".git").exists()))
+        .map(|d| d.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "level = 3\nstrict = true\nprotect = [\".env\"]\noutput = \"out.gitignore\"\n\n[merge]\nmax-combinations = 100\n",
+        )
+        .unwrap();
+        assert_eq!(config.level, Some(3));
+        assert_eq!(config.strict, Some(true));
+        assert_eq!(config.protect, Some(vec![".env".to_string()]));
+        assert_eq!(config.output, Some("out.gitignore".to_string()));
+        assert_eq!(config.merge.max_combinations, Some(100));
+        assert_eq!(config.report, None);
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        // typos are reported instead of being ignored
+        assert!(Config::parse("levle = 3").is_err());
+        assert!(Config::parse("level = \"3\"").is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let root = std::env::temp_dir().join(format!("refactorign_config_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        let root = root.canonicalize().unwrap();
+        assert_eq!(find(&root.join("a/b/.gitignore")), None);
+
+        fs::write(
+            root.join(CONFIG_FILE_NAME),
+            "history = \"history.txt\"\nlevel = 1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            find(&root.join("a/b/.gitignore")),
+            Some(root.join(CONFIG_FILE_NAME))
+        );
+        let config = Config::load(&root.join(CONFIG_FILE_NAME)).unwrap();
+        assert_eq!(
+            config.history,
+            Some(root.join("history.txt").to_string_lossy().to_string())
+        );
+
+        // the one next to the .gitignore file comes first
+        fs::write(root.join("a/b").join(CONFIG_FILE_NAME), "level = 2\n").unwrap();
+        assert_eq!(
+            find(&root.join("a/b/.gitignore")),
+            Some(root.join("a/b").join(CONFIG_FILE_NAME))
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,16 +1,19 @@
-use std::path::PathBuf;
+use std::{collections::BTreeSet, path::PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct File {
     pub name: String,
     pub path: PathBuf,
     pub content: Vec<Line>,
+    pub line_ending: String, // "\n" or "\r\n", as in the original file
+    pub trailing_newline: bool,
+    pub protected: BTreeSet<usize>, // lines no process may remove or rewrite (by `origin`)
 }
 impl File {
     pub fn new(path: PathBuf) -> Self {
-        let name = path.file_name().unwrap().to_str().unwrap().to_string();
-        let content = std::fs::read_to_string(path.clone())
-            .unwrap()
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let text = std::fs::read_to_string(path.clone()).unwrap();
+        let content = text
             .lines()
             .enumerate()
             .map(|(i, l)| Line {
@@ -23,14 +26,75 @@ impl File {
                     }
                 },
                 line_number: i + 1,
+                origin: Some(i + 1),
             })
             .collect::<Vec<Line>>();
+        let protected = protected_lines(&content);
         Self {
             name,
             path,
             content,
+            line_ending: if text.contains("\r\n") { "\r\n" } else { "\n" }.to_string(),
+            trailing_newline: text.ends_with('\n'),
+            protected,
         }
     }
+    pub fn from_lines(path: PathBuf, lines: Vec<String>) -> Self {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let content = lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, l)| Line {
+                content: Content::Pattern(l),
+                line_number: i + 1,
+                origin: Some(i + 1),
+            })
+            .collect::<Vec<Line>>();
+        Self {
+            name,
+            path,
+            content,
+            line_ending: "\n".to_string(),
+            trailing_newline: true,
+            protected: BTreeSet::new(),
+        }
+    }
+    // content with the line endings and the trailing newline of the original file
+    pub fn to_text(&self) -> String {
+        let mut text = self
+            .content
+            .iter()
+            .map(|line| line.content.unwrap())
+            .collect::<Vec<_>>()
+            .join(&self.line_ending);
+        if self.trailing_newline && !self.content.is_empty() {
+            text.push_str(&self.line_ending);
+        }
+        text
+    }
+    // protection follows the line of the original file, not its text (an unprotected copy of a
+    // protected line may be removed)
+    pub fn is_protected(&self, line: &Line) -> bool {
+        line.origin
+            .is_some_and(|origin| self.protected.contains(&origin))
+    }
+    // protect the lines of the original file with the given text
+    pub fn protect(&mut self, lines: &[String]) {
+        let origins = self
+            .content
+            .iter()
+            .filter(|line| lines.iter().any(|l| l == line.content.unwrap()))
+            .filter_map(|line| line.origin)
+            .collect::<Vec<_>>();
+        self.protected.extend(origins);
+    }
+    // index of the line matching the predicate, an unprotected one if any
+    fn find_line(&self, f: impl Fn(&Line) -> bool) -> Option<usize> {
+        self.content
+            .iter()
+            .position(|line| f(line) && !self.is_protected(line))
+            .or_else(|| self.content.iter().position(f))
+    }
     pub fn get_line(&self, i: usize) -> &Line {
         &self.content[i]
     }
@@ -38,6 +102,7 @@ impl File {
         self.content.push(Line {
             content: Content::Pattern(l.clone()),
             line_number: self.content.len() + 1,
+            origin: None,
         });
         if verbose > 0 {
             println!("Added: {}\r\n", l);
@@ -45,16 +110,14 @@ impl File {
     }
     pub fn remove_line(&mut self, l: String, verbose: u8) {
         let i = self
-            .content
-            .iter()
-            .position(|line| match &line.content {
-                Content::Pattern(p) => *p == l,
-                _ => false,
-            })
+            .find_line(|line| matches!(&line.content, Content::Pattern(p) if *p == l))
             .unwrap();
         self.remove_line_with_index(i, verbose);
     }
     pub fn remove_line_with_index(&mut self, i: usize, verbose: u8) {
+        if self.is_protected(&self.content[i]) {
+            return;
+        }
         let removed = self.content.remove(i);
         self.content.iter_mut().for_each(|l| {
             if l.line_number > i {
@@ -67,9 +130,7 @@ impl File {
     }
     pub fn remove_line_with_path(&mut self, path: PathBuf, verbose: u8) {
         let i = self
-            .content
-            .iter()
-            .position(|l| match &l.content {
+            .find_line(|l| match &l.content {
                 Content::Pattern(p) => {
                     // println!("{:?}, {:?}", path, p);
                     let tmp = if path.to_str().unwrap().starts_with("/")
@@ -106,6 +167,9 @@ impl File {
                 _ => false,
             })
             .expect(&format!("{:?}: Path not found: {:?}", self.path, path));
+        if self.is_protected(&self.content[i]) {
+            return;
+        }
         self.content.remove(i);
         self.content.iter_mut().for_each(|l| {
             if l.line_number > i {
@@ -123,7 +187,7 @@ impl File {
             if let Content::Pattern(_) = target {
                 let mut j = i + 1;
                 while j < self.content.len() {
-                    if target == self.get_line(j).content {
+                    if target == self.get_line(j).content && !self.is_protected(self.get_line(j)) {
                         self.remove_line_with_index(j, 0);
                     } else {
                         j += 1;
@@ -134,10 +198,14 @@ impl File {
         }
     }
     pub fn replace_line_with_index(&mut self, i: usize, l: String, verbose: u8) {
+        if self.is_protected(&self.content[i]) {
+            return;
+        }
         let old = self.content[i].content.clone();
         self.content[i] = Line {
             content: Content::Pattern(l.clone()),
             line_number: i + 1,
+            origin: None,
         };
         if verbose > 0 {
             println!("Replaced: {} -> {}\r\n", old.unwrap(), l);
@@ -145,12 +213,7 @@ impl File {
     }
     pub fn replace_line(&mut self, from: String, to: String, verbose: u8) {
         let i = self
-            .content
-            .iter()
-            .position(|line| match &line.content {
-                Content::Pattern(p) => *p == from,
-                _ => false,
-            })
+            .find_line(|line| matches!(&line.content, Content::Pattern(p) if *p == from))
             .unwrap();
         self.replace_line_with_index(i, to, verbose);
     }
@@ -166,10 +229,45 @@ impl File {
     }
 }
 
+// the lines marked by pragmas: `# refactorign: keep` protects the next line,
+// `# refactorign: off` the lines until `# refactorign: on`
+fn protected_lines(content: &[Line]) -> BTreeSet<usize> {
+    let mut protected = BTreeSet::new();
+    let mut off = false;
+    let mut keep = false;
+    for line in content {
+        match &line.content {
+            Content::Comment(c) => match pragma(c) {
+                Some("keep") => keep = true,
+                Some("off") => off = true,
+                Some("on") => off = false,
+                _ => {}
+            },
+            Content::Pattern(_) => {
+                if off || keep {
+                    protected.extend(line.origin);
+                }
+                keep = false;
+            }
+            Content::Blank() => {}
+        }
+    }
+    protected
+}
+
+fn pragma(comment: &str) -> Option<&str> {
+    comment
+        .strip_prefix('#')?
+        .trim()
+        .strip_prefix("refactorign:")
+        .map(|directive| directive.trim())
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Line {
     pub content: Content,
     pub line_number: usize,
+    pub origin: Option<usize>, // line number in the original file (None for lines made by a process)
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -0,0 +1,186 @@
+use std::path::Path;
+
+use regex::{escape, RegexSet};
+
+use super::pattern::{ClassItem, Pattern, Token};
+
+// all patterns of a file compiled into one automaton, so that a path is matched against
+// every pattern in a single pass
+// (the only implementation of the matching rules, built once per pass by the processes)
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    set: RegexSet,
+    patterns: Vec<Pattern>,
+}
+impl Matcher {
+    pub fn new(patterns: &[Pattern]) -> Self {
+        let set = RegexSet::new(patterns.iter().map(to_regex))
+            .unwrap_or_else(|e| panic!("Failed to compile patterns: {}", e));
+        Self {
+            set,
+            patterns: patterns.to_vec(),
+        }
+    }
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+    // indices of the patterns matching the path (relative to the root), in order
+    // negated patterns are matched as their re-included paths
+    pub fn matches(&self, path: &Path, is_dir: bool) -> Vec<usize> {
+        // bytes that are not UTF-8 are matched as replacement characters (by `*` and `?`)
+        let path = path.to_string_lossy();
+        let path = path.trim_start_matches('/').trim_end_matches('/');
+        if path.is_empty() {
+            return Vec::new();
+        }
+        self.set
+            .matches(path)
+            .into_iter()
+            .filter(|i| is_dir || !self.patterns[*i].dir_only)
+            .collect()
+    }
+    pub fn is_match(&self, path: &Path, is_dir: bool) -> bool {
+        !self.matches(path, is_dir).is_empty()
+    }
+    // whether the path is ignored (the last matching pattern decides)
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matches(path, is_dir)
+            .last()
+            .is_some_and(|i| !self.patterns[*i].negated)
+    }
+}
+
+fn class_to_regex(items: &[ClassItem]) -> String {
+    // `[!...]` is the glob spelling of `[^...]`
+    let (negated, items) = match items.first() {
+        Some(ClassItem::Char('!' | '^')) if items.len() > 1 => (true, &items[1..]),
+        _ => (false, items),
+    };
+    let body = items
+        .iter()
+        .map(|item| match item {
+            ClassItem::Char(c) => escape(&c.to_string()),
+            // a reversed range (e.g. `[z-a]`) matches nothing, as in git
+            ClassItem::Range(start, end) if start > end => String::new(),
+            ClassItem::Range(start, end) => {
+                format!(
+                    "{}-{}",
+                    escape(&start.to_string()),
+                    escape(&end.to_string())
+                )
+            }
+        })
+        .collect::<String>();
+    match (negated, body.is_empty()) {
+        (true, true) => "[^/]".to_string(),
+        (false, true) => "[^\\x00-\\x{10FFFF}]".to_string(),
+        _ => format!("[{}{}]", if negated { "^/" } else { "" }, body),
+    }
+}
+
+fn segment_to_regex(segment: &[Token]) -> String {
+    segment
+        .iter()
+        .map(|token| match token {
+            Token::Char(c) | Token::Escaped(c) => escape(&c.to_string()),
+            Token::Any => "[^/]".to_string(),
+            Token::Wildcard => "[^/]*".to_string(),
+            Token::Class(items) => class_to_regex(items),
+        })
+        .collect()
+}
+
+// regular expression matching the paths (relative to the root, without leading or trailing
+// slashes) that the pattern matches
+pub fn to_regex(pattern: &Pattern) -> String {
+    let mut regex = String::from("^");
+    // patterns without a slash match at any depth
+    if !pattern.anchored && pattern.segments.len() == 1 {
+        regex.push_str("(?:.*/)?");
+    }
+    let last = pattern.segments.len().saturating_sub(1);
+    for (i, segment) in pattern.segments.iter().enumerate() {
+        if segment.as_slice() == [Token::Wildcard, Token::Wildcard] {
+            // `**` matches any number of segments
+            regex.push_str(if i < last { "(?:.*/)?" } else { ".*" });
+            continue;
+        }
+        regex.push_str(&segment_to_regex(segment));
+        if i < last {
+            regex.push('/');
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_to_regex() {
+        let cases = vec![
+            ("a", "^(?:.*/)?a$"),
+            ("/a", "^a$"),
+            ("a/b/", "^a/b$"),
+            ("*.txt", "^(?:.*/)?[^/]*\\.txt$"),
+            ("a/b?[0-9]", "^a/b[^/][0-9]$"),
+            ("a/[!x]", "^a/[^/x]$"),
+            ("a/**/b", "^a/(?:.*/)?b$"),
+            ("a/**", "^a/.*$"),
+            ("[z-a]", "^(?:.*/)?[^\\x00-\\x{10FFFF}]$"),
+            ("[z-ab]", "^(?:.*/)?[b]$"),
+            ("[!z-a]", "^(?:.*/)?[^/]$"),
+        ];
+        for (pat, expected) in cases {
+            assert_eq!(
+                to_regex(&Pattern::new_unchecked(pat.to_string())),
+                expected,
+                "Failed: {:?}",
+                pat
+            );
+        }
+    }
+
+    #[test]
+    fn test_matcher() {
+        let patterns = ["*.log", "/build/", "a/*/c", "!keep.log", "d/**/e"]
+            .iter()
+            .map(|p| Pattern::new_unchecked(p.to_string()))
+            .collect::<Vec<_>>();
+        let matcher = Matcher::new(&patterns);
+        let cases = vec![
+            ("x.log", false, vec![0], true),
+            ("a/x.log", false, vec![0], true),
+            ("keep.log", false, vec![0, 3], false),
+            ("build", true, vec![1], true),
+            ("build", false, vec![], false),
+            ("x/build", true, vec![], false),
+            ("a/b/c", false, vec![2], true),
+            ("a/b/b/c", false, vec![], false),
+            ("d/e", false, vec![4], true),
+            ("d/x/y/e", false, vec![4], true),
+        ];
+        for (path, is_dir, expected, ignored) in cases {
+            assert_eq!(
+                matcher.matches(Path::new(path), is_dir),
+                expected,
+                "Failed: {:?}",
+                path
+            );
+            assert_eq!(matcher.is_ignored(Path::new(path), is_dir), ignored);
+        }
+    }
+
+    #[test]
+    fn test_reversed_range() {
+        // valid lines, so compiling them should not fail
+        let matcher = Matcher::new(&[
+            Pattern::new("[z-a]".to_string()),
+            Pattern::new_unchecked("x[!z-a]".to_string()),
+        ]);
+        assert!(!matcher.is_match(Path::new("a"), false));
+        assert!(!matcher.is_match(Path::new("z"), false));
+        assert_eq!(matcher.matches(Path::new("xa"), false), vec![1]);
+    }
+}
@@ -1,4 +1,4 @@
-use super::pattern::Kind;
+use super::pattern::{ClassItem, Kind, Pattern, Token};
 
 peg::parser! {
     grammar pattern_parser() for str {
@@ -34,6 +34,40 @@ peg::parser! {
     }
 }
 
+// structure of a line (accepts any glob, unlike pattern_parser)
+peg::parser! {
+    grammar ast_parser() for str {
+        pub rule pattern() -> Pattern
+            = negated:"!"? anchored:"/"? segments:(segment() ** "/") dir_only:"/"? {
+                Pattern::from_parts(
+                    negated.is_some(),
+                    anchored.is_some(),
+                    segments,
+                    dir_only.is_some(),
+                )
+            }
+
+        rule segment() -> Vec<Token> = token()+
+
+        rule token() -> Token
+            = "*" { Token::Wildcard }
+            / "?" { Token::Any }
+            / "\\" c:[_] { Token::Escaped(c) }
+            / "[" items:class_item()+ "]" { Token::Class(items) }
+            / c:[^ ('/'|'['|']'|'*'|'?'|'\\')] { Token::Char(c) }
+
+        rule class_item() -> ClassItem
+            = start:class_char() "-" end:class_char() { ClassItem::Range(start, end) }
+            / c:class_char() { ClassItem::Char(c) }
+
+        rule class_char() -> char = [^ (']'|'['|'/')]
+    }
+}
+
+pub fn parse_ast(l: &str) -> Option<Pattern> {
+    ast_parser::pattern(l).ok()
+}
+
 #[allow(unused_variables)]
 pub fn parse(l: &str) -> Option<Kind> {
     let stripped = l.strip_suffix("/").unwrap_or(l);
@@ -52,6 +86,154 @@ pub fn parse(l: &str) -> Option<Kind> {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// problem found on a line of a .gitignore file (line and column are 1-based)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line_number: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub reason: String,
+}
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}: {}",
+            self.line_number,
+            self.column,
+            match self.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            self.reason
+        )
+    }
+}
+
+// column (1-based) and reason of the first problem of a line `parse` rejects
+fn invalid_reason(l: &str) -> (usize, String) {
+    let column = |i: usize| l[..i].chars().count() + 1;
+    if let Some(i) = l.find("**") {
+        return (column(i), "`**` is not supported".to_string());
+    }
+    if l.starts_with("!!") {
+        return (2, "a line cannot be negated twice".to_string());
+    }
+    if let Some(i) = l.find("//") {
+        return (column(i + 1), "empty path segment".to_string());
+    }
+    // brackets
+    let mut open = None;
+    for (i, c) in l.char_indices() {
+        match (c, open) {
+            ('[', None) => open = Some(i),
+            ('[', Some(_)) => return (column(i), "nested `[`".to_string()),
+            (']', Some(start)) if i == start + 1 => {
+                return (column(start), "empty character class".to_string())
+            }
+            (']', Some(_)) => open = None,
+            (']', None) => return (column(i), "unbalanced `]`".to_string()),
+            ('/', Some(start)) => {
+                return (column(start), "`/` inside a character class".to_string())
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = open {
+        return (column(start), "unbalanced `[`".to_string());
+    }
+    if let Some(i) = l.find('*') {
+        if l.starts_with('!') {
+            return (1, "negated wildcard is not supported".to_string());
+        }
+        return (
+            column(i),
+            "`*` is only supported at the start of a single-segment pattern (e.g. `*.log`)"
+                .to_string(),
+        );
+    }
+    if let Some((i, c)) = l
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '!' || *c == '#')
+    {
+        return (column(i), format!("`{}` is only supported at the start of a line", c));
+    }
+    if let Some((i, c)) = l
+        .char_indices()
+        .find(|(_, c)| matches!(c, '^' | '$' | '+' | '|' | '(' | ')' | '\\' | '?'))
+    {
+        return (column(i), format!("unsupported character `{}`", c));
+    }
+    let stripped = l.strip_suffix("/").unwrap_or(l);
+    match pattern_parser::pattern(stripped) {
+        Err(e) => (e.location.column, format!("expected {}", e.expected)),
+        Ok(_) => (1, "invalid pattern".to_string()),
+    }
+}
+
+// valid lines that probably do not do what they look like
+fn suspicious_reasons(l: &str) -> Vec<(usize, String)> {
+    let mut reasons = Vec::new();
+    let trimmed = l.trim_end_matches([' ', '\t']);
+    if trimmed.len() < l.len() && !trimmed.ends_with('\\') {
+        reasons.push((
+            trimmed.chars().count() + 1,
+            "trailing whitespace is ignored by git".to_string(),
+        ));
+    }
+    let mut offset = 0;
+    for segment in l.split('/') {
+        if segment == ".." || segment == "." {
+            reasons.push((
+                l[..offset].chars().count() + 1,
+                format!(
+                    "`{}` segments never match (paths are relative to the .gitignore directory)",
+                    segment
+                ),
+            ));
+        }
+        offset += segment.len() + 1;
+    }
+    reasons
+}
+
+// every invalid line of a file, and the suspicious ones
+pub fn validate(content: &str) -> Vec<Diagnostic> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty() && !l.starts_with('#'))
+        .flat_map(|(i, l)| {
+            if parse(l).is_none() {
+                let (column, reason) = invalid_reason(l);
+                vec![Diagnostic {
+                    line_number: i + 1,
+                    column,
+                    severity: Severity::Error,
+                    reason,
+                }]
+            } else {
+                suspicious_reasons(l)
+                    .into_iter()
+                    .map(|(column, reason)| Diagnostic {
+                        line_number: i + 1,
+                        column,
+                        severity: Severity::Warning,
+                        reason,
+                    })
+                    .collect()
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,4 +286,77 @@ mod tests {
             assert!(parse(p).is_none(), "Failed: {:?}", p);
         }
     }
+
+    #[test]
+    fn test_parse_ast() {
+        let cases = [
+            "a",
+            "/a/b/",
+            "!a/b",
+            "!/b.txt",
+            "*.py[cod]",
+            "a[1-3A-C]/b/c",
+            "a/*",
+            "a/b*c?d",
+            "a\\#b",
+            "/",
+            "",
+        ];
+        for l in cases.into_iter() {
+            let pattern = parse_ast(l).unwrap();
+            assert_eq!(pattern.to_string(), l, "Failed: {:?}", l);
+        }
+        let pattern = parse_ast("!/a/b[1-3x]/").unwrap();
+        assert!(pattern.negated && pattern.anchored && pattern.dir_only);
+        assert_eq!(pattern.path, "a/b[1-3x]");
+        assert_eq!(
+            pattern.segments[1],
+            vec![
+                Token::Char('b'),
+                Token::Class(vec![ClassItem::Range('1', '3'), ClassItem::Char('x')])
+            ]
+        );
+        assert_eq!(pattern.kind, Kind::Negation(Box::new(Kind::Normal)));
+        assert!(parse_ast("a//").is_none());
+        assert!(parse_ast("[a").is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        let content = [
+            "# comment",
+            "a",
+            "**",
+            "a/**/b",
+            "[a-z",
+            "a]",
+            "!*.txt",
+            "a/*",
+            "!!a",
+            "a//b",
+            "",
+            "b  ",
+            "a/../b",
+            "a?",
+        ]
+        .join("\n");
+        let diagnostics = validate(&content)
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        let expected = [
+            "line 3, column 1: error: `**` is not supported",
+            "line 4, column 3: error: `**` is not supported",
+            "line 5, column 1: error: unbalanced `[`",
+            "line 6, column 2: error: unbalanced `]`",
+            "line 7, column 1: error: negated wildcard is not supported",
+            "line 8, column 3: error: `*` is only supported at the start of a single-segment pattern (e.g. `*.log`)",
+            "line 9, column 2: error: a line cannot be negated twice",
+            "line 10, column 3: error: empty path segment",
+            "line 12, column 2: warning: trailing whitespace is ignored by git",
+            "line 13, column 3: warning: `..` segments never match (paths are relative to the .gitignore directory)",
+            "line 14, column 2: error: unsupported character `?`",
+        ];
+        assert_eq!(diagnostics, expected);
+    }
 }
@@ -1,231 +1,358 @@
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
 
 use super::parse;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Pattern {
     pub kind: Kind,
-    pub path: String, // leading '/', '!', or both + trailing "/" are stripped
+    pub path: String,   // leading '/', '!', or both + trailing "/" are stripped
+    pub negated: bool,  // leading '!'
+    pub anchored: bool, // leading '/'
+    pub dir_only: bool, // trailing '/'
+    pub segments: Vec<Vec<Token>>,
 }
 impl Pattern {
+    // panics on an invalid line (instead of exiting), so that a batch run only fails the file
     pub fn new(l: String) -> Self {
         match parse::parse(&l) {
-            Some(k) => Self {
-                kind: k,
-                path: remove_slash(&l),
-            },
-            None => {
-                eprintln!("Invalid pattern found. Aborting.\r\nInvalid pattern: {}", l);
-                std::process::exit(1);
-            }
+            Some(_) => Self::new_unchecked(l),
+            None => panic!("Invalid pattern found: {}", l),
         }
     }
-}
-
-fn remove_slash(l: &str) -> String {
-    let mut l = l.to_string();
-    if l.starts_with('!') {
-        l.remove(0);
+    // for lines produced by the processes (e.g. `dir/*`), which the parser does not accept
+    pub fn new_unchecked(l: String) -> Self {
+        parse::parse_ast(&l).unwrap_or_else(|| {
+            // keep unsupported syntax as literal characters, so that the line is not altered
+            let negated = l.starts_with('!');
+            let l = l.strip_prefix('!').unwrap_or(&l);
+            let segments = l
+                .strip_prefix('/')
+                .unwrap_or(l)
+                .split('/')
+                .map(|s| s.chars().map(Token::Char).collect())
+                .collect();
+            Self::from_parts(negated, l.starts_with('/'), segments, false)
+        })
+    }
+    pub fn from_parts(
+        negated: bool,
+        anchored: bool,
+        segments: Vec<Vec<Token>>,
+        dir_only: bool,
+    ) -> Self {
+        Self {
+            kind: Kind::Normal,
+            path: String::new(),
+            negated,
+            anchored,
+            dir_only,
+            segments,
+        }
+        .update()
+    }
+    // literal pattern for a path relative to the root
+    // special characters are escaped (e.g. a file named `a[1].txt` -> `a\[1\].txt`)
+    pub fn from_path(path: &Path) -> Self {
+        let segments = path
+            .iter()
+            .filter(|s| !s.is_empty() && *s != "/")
+            .enumerate()
+            .map(|(i, s)| {
+                let literal = |(j, c): (usize, char)| match c {
+                    '*' | '?' | '[' | ']' | '\\' => Token::Escaped(c),
+                    // a leading `!` or `#` would make a negation or a comment
+                    '!' | '#' if i == 0 && j == 0 => Token::Escaped(c),
+                    c => Token::Char(c),
+                };
+                match s.to_str() {
+                    Some(s) => s.chars().enumerate().map(literal).collect(),
+                    // bytes that are not UTF-8 cannot be written in the file, so a wildcard
+                    // stands for each run of them
+                    None => s
+                        .to_string_lossy()
+                        .chars()
+                        .enumerate()
+                        .map(|(j, c)| match c {
+                            char::REPLACEMENT_CHARACTER => Token::Wildcard,
+                            c => literal((j, c)),
+                        })
+                        .dedup_by(|a, b| *a == Token::Wildcard && *b == Token::Wildcard)
+                        .collect(),
+                }
+            })
+            .collect();
+        Self::from_parts(false, false, segments, false)
+    }
+    // anchor to the root, so that the pattern is not matched at any depth
+    pub fn anchor(self) -> Self {
+        Self {
+            anchored: true,
+            ..self
+        }
+        .update()
     }
-    if l.starts_with('/') {
-        l.remove(0);
+    // anchor only if needed (patterns with a slash in the middle are already anchored)
+    pub fn anchor_top_level(self) -> Self {
+        if self.segments.len() <= 1 {
+            self.anchor()
+        } else {
+            self
+        }
+    }
+    pub fn negate(self) -> Self {
+        Self {
+            negated: true,
+            ..self
+        }
+        .update()
     }
-    if l.ends_with('/') {
-        l.pop();
+    pub fn with_dir_only(self, dir_only: bool) -> Self {
+        Self { dir_only, ..self }.update()
+    }
+    pub fn join(mut self, segment: Vec<Token>) -> Self {
+        self.segments.push(segment);
+        self.update()
+    }
+    pub fn last_segment(&self) -> &[Token] {
+        self.segments.last().map(|s| s.as_slice()).unwrap_or(&[])
+    }
+    pub fn with_last_segment(mut self, segment: Vec<Token>) -> Self {
+        match self.segments.last_mut() {
+            Some(last) => *last = segment,
+            None => self.segments.push(segment),
+        }
+        self.update()
+    }
+    // (segment, token) starting at the character index of the line
+    // the index just after the last token of a segment gives the length of the segment
+    pub fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        let mut offset = self.negated as usize + self.anchored as usize;
+        for (i, segment) in self.segments.iter().enumerate() {
+            for (j, token) in segment.iter().enumerate() {
+                if offset == index {
+                    return Some((i, j));
+                }
+                offset += token.width();
+            }
+            if offset == index {
+                return Some((i, segment.len()));
+            }
+            offset += 1;
+        }
+        None
+    }
+    // recompute kind and path from the structure
+    fn update(mut self) -> Self {
+        let kind = if self.anchored || self.segments.len() != 1 {
+            Kind::Normal
+        } else if self.segments[0].first() == Some(&Token::Wildcard) {
+            Kind::Wildcard
+        } else {
+            Kind::Global
+        };
+        self.kind = if self.negated {
+            Kind::Negation(Box::new(kind))
+        } else {
+            kind
+        };
+        self.path = segments_to_string(&self.segments);
+        self
     }
-    l
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Kind {
-    Negation(Box<Kind>),
-    Wildcard,
-    Global,
-    Normal,
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}{}",
+            if self.negated { "!" } else { "" },
+            if self.anchored { "/" } else { "" },
+            segments_to_string(&self.segments),
+            if self.dir_only { "/" } else { "" }
+        )
+    }
 }
 
-pub fn expand_range(pat: String) -> Vec<String> {
-    if !(pat.find('[').is_some() && pat.find(']').is_some()) {
-        return vec![pat];
-    }
-
-    fn cat(l: &str, rg: &str, r: &str) -> HashSet<String> {
-        let mut res = HashSet::new();
-        let mut chars = Vec::new();
-        let mut rg_chars: Vec<char> = rg.chars().collect();
-        let mut i = 0;
-
-        while i < rg_chars.len() {
-            if rg_chars[i] == '-' {
-                let start = rg_chars[i - 1] as u8;
-                let end = rg_chars[i + 1] as u8;
-                chars.extend((start..=end).map(|x| x as char));
-                rg_chars.drain(i - 1..=i + 1);
-                if i < 2 {
-                    break;
-                }
-                i -= 2;
-            }
-            i += 1;
-        }
-        chars.extend(rg_chars);
-        let chars_set: HashSet<_> = chars.into_iter().collect();
+fn segments_to_string(segments: &[Vec<Token>]) -> String {
+    segments
+        .iter()
+        .map(|segment| segment.iter().map(|t| t.to_string()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("/")
+}
 
-        for c in chars_set {
-            res.insert(format!("{}{}{}", l, c, r));
-        }
-        res
-    }
-
-    let mut res = cat(
-        &pat[..pat.find('[').unwrap()],
-        &pat[pat.find('[').unwrap() + 1..pat.find(']').unwrap()],
-        &pat[pat.find(']').unwrap() + 1..],
-    );
-
-    loop {
-        let mut tmp = HashSet::new();
-        for p in res.clone() {
-            if let (Some(start), Some(end)) = (p.find('['), p.find(']')) {
-                res.remove(&p);
-                let expanded = cat(&p[..start], &p[start + 1..end], &p[end + 1..]);
-                tmp.extend(expanded);
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum Token {
+    Char(char),
+    Escaped(char),         // '\' + char
+    Any,                   // '?'
+    Wildcard,              // '*'
+    Class(Vec<ClassItem>), // '[' ... ']'
+}
+impl Token {
+    // number of characters in the line
+    pub fn width(&self) -> usize {
+        self.to_string().chars().count()
+    }
+    // characters matched by a class (or by a single character)
+    // None if they cannot be listed (e.g. `*` or `[!abc]`, matching anything but a, b and c)
+    pub fn chars(&self) -> Option<Vec<char>> {
+        match self {
+            Token::Char(c) | Token::Escaped(c) => Some(vec![*c]),
+            Token::Class(items)
+                if items.len() > 1 && matches!(items[0], ClassItem::Char('!' | '^')) =>
+            {
+                None
             }
+            Token::Class(items) => Some(
+                items
+                    .iter()
+                    .flat_map(|item| match item {
+                        ClassItem::Char(c) => vec![*c],
+                        ClassItem::Range(start, end) => (*start..=*end).collect(),
+                    })
+                    .collect(),
+            ),
+            _ => None,
         }
-        if tmp.is_empty() {
-            break;
-        }
-        res.extend(tmp);
     }
-    res.into_iter().collect()
 }
 
-pub fn does_match(path_raw: &PathBuf, pat_raw: &String) -> bool {
-    let tmp = if path_raw.to_str().unwrap().starts_with("/")
-        && path_raw
-            .to_str()
-            .unwrap()
-            .strip_suffix("/")
-            .unwrap_or(path_raw.to_str().unwrap())[1..]
-            .contains("/")
-    {
-        path_raw.strip_prefix("/").unwrap().to_path_buf()
-    } else {
-        path_raw.to_path_buf()
-    };
-    let path = {
-        let tmp_str = tmp.to_str().unwrap();
-        if tmp_str.ends_with("/") && tmp_str != "/" {
-            PathBuf::from(tmp_str.strip_suffix("/").unwrap())
-        } else {
-            tmp
-        }
-    };
-    let tmp = if pat_raw.starts_with("/")
-        && pat_raw.strip_suffix("/").unwrap_or(pat_raw)[1..].contains("/")
-    {
-        pat_raw.strip_prefix("/").unwrap()
-    } else {
-        pat_raw
-    };
-    let pat = tmp.strip_suffix("/").unwrap_or(tmp);
-    // println!("path: {:?}, pat: {}", path, pat);
-    let mut path_it = path.to_str().unwrap().chars();
-    let mut pat_it = pat.chars();
-    'outer: loop {
-        match (path_it.next(), pat_it.next()) {
-            (Some(_), Some('*')) => {
-                if let Some(pat_next) = pat_it.clone().next() {
-                    while let Some(c) = path_it.next() {
-                        if c == pat_next {
-                            pat_it.next();
-                            continue 'outer;
-                        }
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Char(c) => write!(f, "{}", c),
+            Token::Escaped(c) => write!(f, "\\{}", c),
+            Token::Any => write!(f, "?"),
+            Token::Wildcard => write!(f, "*"),
+            Token::Class(items) => {
+                write!(f, "[")?;
+                for item in items {
+                    match item {
+                        ClassItem::Char(c) => write!(f, "{}", c)?,
+                        ClassItem::Range(start, end) => write!(f, "{}-{}", start, end)?,
                     }
-                    return false;
-                } else {
-                    return true;
                 }
+                write!(f, "]")
             }
-            (Some(p), Some('[')) => {
-                let mut rg = "[".to_string();
-                while let Some(c) = pat_it.next() {
-                    rg.push(c);
-                    if c == ']' {
-                        break;
-                    }
-                }
-                let expanded = expand_range(rg);
-                if expanded.iter().any(|e| e.starts_with(p)) {
-                    continue 'outer;
-                } else {
-                    return false;
-                }
-            }
-            (Some(p), Some(p2)) => {
-                if p != p2 {
-                    return false;
-                }
-            }
-            (None, None) => return true,
-            _ => return false,
         }
     }
 }
 
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum ClassItem {
+    Char(char),
+    Range(char, char),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Kind {
+    Negation(Box<Kind>),
+    Wildcard,
+    Global,
+    Normal,
+}
+
 pub trait ToString {
     fn to_string(&self) -> String;
 }
 
 impl ToString for PathBuf {
     fn to_string(&self) -> String {
-        self.to_str().unwrap().to_string()
+        self.to_string_lossy().to_string()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::matcher::Matcher;
+    fn is_match(pattern: &Pattern, path: &Path, is_dir: bool) -> bool {
+        Matcher::new(std::slice::from_ref(pattern)).is_match(path, is_dir)
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid pattern found: [a-z")]
+    fn test_new_invalid() {
+        Pattern::new("[a-z".to_string());
+    }
+
     #[test]
-    fn test_expand_range() {
+    fn test_from_path() {
         let cases = vec![
-            ("[123a-d]", vec!["1", "2", "3", "a", "b", "c", "d"]),
-            ("a[abc]d", vec!["abd", "acd", "aad"]),
-            ("a[abc-ef]d", vec!["aad", "abd", "acd", "add", "aed", "afd"]),
-            (
-                "a[abc-ef][123]",
-                vec![
-                    "aa1", "aa2", "aa3", "ab1", "ab2", "ab3", "ac1", "ac2", "ac3", "ad1", "ad2",
-                    "ad3", "ae1", "ae2", "ae3", "af1", "af2", "af3",
-                ],
-            ),
+            ("a/b.txt", "a/b.txt"),
+            ("a/b[1].txt", "a/b\\[1\\].txt"),
+            ("a/*?\\", "a/\\*\\?\\\\"),
+            ("!a/b!", "\\!a/b!"),
+            ("#a/#b", "\\#a/#b"),
         ];
-        for (pat, mut expected) in cases {
-            let mut expanded = expand_range(pat.to_string());
-            expanded.sort();
-            expected.sort();
-            assert_eq!(expanded, expected);
+        for (path, expected) in cases {
+            let pattern = Pattern::from_path(Path::new(path));
+            assert_eq!(pattern.to_string(), expected);
+            // the line matches the path itself, and nothing else
+            let line = Pattern::new_unchecked(pattern.to_string());
+            assert_eq!(line, pattern, "Failed: {:?}", path);
+            assert!(
+                is_match(&line, Path::new(path), false),
+                "Failed: {:?}",
+                path
+            );
         }
+        assert!(!is_match(
+            &Pattern::from_path(Path::new("a/b[1].txt")),
+            Path::new("a/b1.txt"),
+            false
+        ));
+    }
+
+    #[test]
+    fn test_chars() {
+        let chars = |token: &str| Pattern::new_unchecked(token.to_string()).segments[0][0].chars();
+        assert_eq!(chars("a"), Some(vec!['a']));
+        assert_eq!(chars("[a-c]"), Some(vec!['a', 'b', 'c']));
+        assert_eq!(chars("[!a-c]"), None);
+        assert_eq!(chars("[^a]"), None);
+        assert_eq!(chars("*"), None);
     }
 
     #[test]
-    fn test_does_match() {
+    fn test_is_match() {
         let cases = vec![
-            ("a/b", "a/b", true),
-            ("a/*.txt", "a/b.txt", true),
-            ("a/*.txt", "a/abc.txt", true),
-            ("a/*.txt", "a/.txt", false),
-            ("a/[a-d].txt", "a/b.txt", true),
-            ("a/[1-3a-d].txt", "a/e.txt", false),
-            ("a/*.py[cod]", "a/test.pyd", true),
-            ("a/*.py[cod]", "a/test.pyw", false),
-            ("*", "a", true),
-            ("/a/b", "a/b/", true),
-            ("a/b/", "a/b", true),
-            ("/.gradle/", "/.gradle/", true),
+            ("a", "a", false, true),
+            ("a", "b/a", false, true),
+            ("*.txt", "b/a.txt", false, true),
+            ("/a", "a", false, true),
+            ("/a", "b/a", false, false),
+            ("a/*", "a/b", false, true),
+            ("a/*", "a/b/c", false, false),
+            ("a/[b-d]", "a/c", false, true),
+            ("a/[1-3a-d].txt", "a/e.txt", false, false),
+            ("a/*.py[cod]", "a/test.pyd", false, true),
+            ("a/*.py[cod]", "a/test.pyw", false, false),
+            ("a/*.txt", "a/.txt", false, true),
+            ("!a/b", "a/b", false, true),
+            ("a/b/", "a/b", true, true),
+            ("a/b/", "a/b", false, false),
+            ("logs/", "a/logs", true, true),
+            ("logs/", "a/logs", false, false),
         ];
-        for (pat, path, expected) in cases {
-            assert_eq!(does_match(&PathBuf::from(path), &pat.to_string()), expected);
+        for (pat, path, is_dir, expected) in cases {
+            assert_eq!(
+                is_match(
+                    &Pattern::new_unchecked(pat.to_string()),
+                    Path::new(path),
+                    is_dir
+                ),
+                expected,
+                "Failed: {:?}, {:?}",
+                pat,
+                path
+            );
         }
     }
 }
@@ -0,0 +1,435 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use super::pattern::{ClassItem, Pattern, Token};
+
+// inclusion between the sets of paths matched by patterns, for every possible tree
+// a path is spelled as its characters, '/' between the segments and a marker telling whether
+// it is a directory, so that each pattern becomes a small automaton over that alphabet
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Symbol {
+    Char(char),
+    Slash,
+    End(bool), // end of the path (whether it is a directory)
+}
+
+#[derive(Debug, Clone)]
+enum Label {
+    Token(Token), // a character of a segment
+    SegmentChar,  // any character of a segment
+    PathChar,     // any character, including '/'
+    Slash,
+    End(Option<bool>), // end of a path (directory / file / either)
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    edges: Vec<(Label, usize)>,
+    eps: Vec<usize>,
+    accept: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Nfa {
+    states: Vec<State>,
+}
+
+fn class_matches(items: &[ClassItem], c: char) -> bool {
+    // `[!...]` and `[^...]` match the characters not listed
+    let (negated, items) = match items.first() {
+        Some(ClassItem::Char('!' | '^')) if items.len() > 1 => (true, &items[1..]),
+        _ => (false, items),
+    };
+    let found = items.iter().any(|item| match item {
+        ClassItem::Char(x) => *x == c,
+        ClassItem::Range(start, end) => *start <= c && c <= *end,
+    });
+    found != negated
+}
+
+impl Label {
+    fn matches(&self, symbol: Symbol) -> bool {
+        match (self, symbol) {
+            (Label::Token(token), Symbol::Char(c)) => match token {
+                Token::Char(x) | Token::Escaped(x) => *x == c,
+                Token::Any | Token::Wildcard => true,
+                Token::Class(items) => class_matches(items, c),
+            },
+            (Label::SegmentChar, Symbol::Char(_)) => true,
+            (Label::PathChar, Symbol::Char(_) | Symbol::Slash) => true,
+            (Label::Slash, Symbol::Slash) => true,
+            (Label::End(dir), Symbol::End(is_dir)) => dir.is_none() || *dir == Some(is_dir),
+            _ => false,
+        }
+    }
+}
+
+impl Nfa {
+    fn add(&mut self) -> usize {
+        self.states.push(State::default());
+        self.states.len() - 1
+    }
+    fn edge(&mut self, from: usize, label: Label, to: usize) {
+        self.states[from].edges.push((label, to));
+    }
+    fn eps(&mut self, from: usize, to: usize) {
+        self.states[from].eps.push(to);
+    }
+    fn new(pattern: &Pattern) -> Self {
+        let mut nfa = Nfa { states: Vec::new() };
+        let mut current = nfa.add();
+        // patterns without a slash match at any depth
+        if !pattern.anchored && pattern.segments.len() == 1 {
+            let inner = nfa.add();
+            let next = nfa.add();
+            nfa.edge(current, Label::SegmentChar, inner);
+            nfa.edge(inner, Label::SegmentChar, inner);
+            nfa.edge(inner, Label::Slash, current);
+            nfa.eps(current, next);
+            current = next;
+        }
+        let last = pattern.segments.len().saturating_sub(1);
+        for (i, segment) in pattern.segments.iter().enumerate() {
+            if segment.as_slice() == [Token::Wildcard, Token::Wildcard] {
+                // `**` matches any number of segments
+                nfa.edge(current, Label::PathChar, current);
+                if i < last {
+                    let next = nfa.add();
+                    nfa.edge(current, Label::Slash, next);
+                    nfa.eps(current, next);
+                    current = next;
+                }
+                continue;
+            }
+            for token in segment {
+                if *token == Token::Wildcard {
+                    let next = nfa.add();
+                    nfa.edge(current, Label::SegmentChar, current);
+                    nfa.eps(current, next);
+                    current = next;
+                } else {
+                    let next = nfa.add();
+                    nfa.edge(current, Label::Token(token.clone()), next);
+                    current = next;
+                }
+            }
+            if i < last {
+                let next = nfa.add();
+                nfa.edge(current, Label::Slash, next);
+                current = next;
+            }
+        }
+        // the matched path itself, or anything inside it (then it is a directory)
+        let accept = nfa.add();
+        nfa.states[accept].accept = true;
+        nfa.edge(
+            current,
+            Label::End(if pattern.dir_only { Some(true) } else { None }),
+            accept,
+        );
+        let inside = nfa.add();
+        let inner = nfa.add();
+        nfa.edge(current, Label::Slash, inside);
+        nfa.edge(inside, Label::SegmentChar, inner);
+        nfa.edge(inner, Label::SegmentChar, inner);
+        nfa.edge(inner, Label::Slash, inside);
+        nfa.edge(inner, Label::End(None), accept);
+        nfa
+    }
+    fn closure(&self, states: BTreeSet<usize>) -> BTreeSet<usize> {
+        let mut result = states.clone();
+        let mut stack = states.into_iter().collect::<Vec<_>>();
+        while let Some(s) = stack.pop() {
+            for next in self.states[s].eps.iter() {
+                if result.insert(*next) {
+                    stack.push(*next);
+                }
+            }
+        }
+        result
+    }
+    fn start(&self) -> BTreeSet<usize> {
+        self.closure(BTreeSet::from([0]))
+    }
+    fn step(&self, states: &BTreeSet<usize>, symbol: Symbol) -> BTreeSet<usize> {
+        self.closure(
+            states
+                .iter()
+                .flat_map(|s| self.states[*s].edges.iter())
+                .filter(|(label, _)| label.matches(symbol))
+                .map(|(_, next)| *next)
+                .collect(),
+        )
+    }
+    fn accepts(&self, states: &BTreeSet<usize>) -> bool {
+        states.iter().any(|s| self.states[*s].accept)
+    }
+}
+
+// one character for each group of characters that no pattern tells apart
+fn alphabet(patterns: &[&Pattern]) -> Vec<Symbol> {
+    let next = |c: char| char::from_u32(c as u32 + 1);
+    let mut bounds = BTreeSet::from([Some('\u{1}'), Some('/'), next('/')]);
+    for token in patterns.iter().flat_map(|p| p.segments.iter().flatten()) {
+        match token {
+            Token::Char(c) | Token::Escaped(c) => {
+                bounds.extend([Some(*c), next(*c)]);
+            }
+            Token::Class(items) => {
+                for item in items {
+                    match item {
+                        ClassItem::Char(c) => bounds.extend([Some(*c), next(*c)]),
+                        ClassItem::Range(start, end) => bounds.extend([Some(*start), next(*end)]),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    // prefer a readable character of each group, so that examples are easy to read
+    let bounds = bounds.into_iter().flatten().collect::<Vec<_>>();
+    bounds
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| **c != '/')
+        .map(|(i, lo)| {
+            let hi = bounds.get(i + 1).copied().unwrap_or(char::MAX);
+            "abcdefghijklmnopqrstuvwxyz0123456789_-."
+                .chars()
+                .find(|c| lo <= c && *c < hi)
+                .unwrap_or(*lo)
+        })
+        .map(Symbol::Char)
+        .chain([Symbol::Slash, Symbol::End(false), Symbol::End(true)])
+        .collect()
+}
+
+// shortest paths matched by the pattern but by none of the patterns (at most `limit`)
+// patterns of the other polarity (negated or not) are not taken into account
+fn search(patterns: &[Pattern], pattern: &Pattern, limit: usize) -> Vec<Vec<Symbol>> {
+    let patterns = patterns
+        .iter()
+        .filter(|p| p.negated == pattern.negated)
+        .collect::<Vec<_>>();
+    let symbols = alphabet(&[patterns.as_slice(), &[pattern]].concat());
+    let nfa = Nfa::new(pattern);
+    let nfas = patterns.iter().map(|p| Nfa::new(p)).collect::<Vec<_>>();
+    let differs = |(states, others): &(BTreeSet<usize>, Vec<BTreeSet<usize>>)| {
+        nfa.accepts(states) && !nfas.iter().zip(others.iter()).any(|(n, s)| n.accepts(s))
+    };
+    let start = (
+        nfa.start(),
+        nfas.iter().map(|n| n.start()).collect::<Vec<_>>(),
+    );
+    let mut found = Vec::new();
+    // each state is expanded up to `limit` times, so that several examples can be found
+    let mut visited = HashMap::from([(start.clone(), 1)]);
+    let mut queue = VecDeque::from([(start, Vec::new())]);
+    while let Some(((states, others), word)) = queue.pop_front() {
+        for symbol in symbols.iter() {
+            let next = nfa.step(&states, *symbol);
+            if next.is_empty() {
+                continue;
+            }
+            let next = (
+                next,
+                nfas.iter()
+                    .zip(others.iter())
+                    .map(|(n, s)| n.step(s, *symbol))
+                    .collect::<Vec<_>>(),
+            );
+            let mut next_word = word.clone();
+            next_word.push(*symbol);
+            if differs(&next) {
+                found.push(next_word);
+                if found.len() >= limit {
+                    return found;
+                }
+            } else {
+                let count = visited.entry(next.clone()).or_insert(0);
+                if *count < limit {
+                    *count += 1;
+                    queue.push_back((next, next_word));
+                }
+            }
+        }
+    }
+    found
+}
+
+// whether the patterns together match every path the pattern matches
+// patterns of the other polarity (negated or not) are not taken into account
+pub fn subsumes_all(patterns: &[Pattern], pattern: &Pattern) -> bool {
+    search(patterns, pattern, 1).is_empty()
+}
+
+// examples of paths (relative to the root, directories end with '/') matched by the pattern
+// but by none of the patterns
+pub fn differences(patterns: &[Pattern], pattern: &Pattern, limit: usize) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for word in search(patterns, pattern, limit * 2) {
+        let path = word
+            .iter()
+            .map(|symbol| match symbol {
+                Symbol::Char(c) => c.to_string(),
+                Symbol::Slash => "/".to_string(),
+                Symbol::End(true) => "/".to_string(),
+                Symbol::End(false) => String::new(),
+            })
+            .collect::<String>();
+        // the same path as a file and as a directory is shown once
+        if !paths
+            .iter()
+            .any(|p| p.trim_end_matches('/') == path.trim_end_matches('/'))
+        {
+            paths.push(path);
+        }
+    }
+    paths.truncate(limit);
+    paths
+}
+
+// whether `a` matches every path `b` matches
+pub fn subsumes(a: &Pattern, b: &Pattern) -> bool {
+    subsumes_all(std::slice::from_ref(a), b)
+}
+
+// whether some path is matched by both patterns, whether they are negated or not
+pub fn overlaps(a: &Pattern, b: &Pattern) -> bool {
+    let symbols = alphabet(&[a, b]);
+    let (nfa_a, nfa_b) = (Nfa::new(a), Nfa::new(b));
+    let start = (nfa_a.start(), nfa_b.start());
+    let mut visited = HashSet::from([start.clone()]);
+    let mut queue = VecDeque::from([start]);
+    while let Some((states_a, states_b)) = queue.pop_front() {
+        if nfa_a.accepts(&states_a) && nfa_b.accepts(&states_b) {
+            return true;
+        }
+        for symbol in symbols.iter() {
+            let next = (
+                nfa_a.step(&states_a, *symbol),
+                nfa_b.step(&states_b, *symbol),
+            );
+            if !next.0.is_empty() && !next.1.is_empty() && visited.insert(next.clone()) {
+                queue.push_back(next);
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    #[test]
+    fn test_subsumes() {
+        let cases = vec![
+            ("a", "a", true),
+            ("a", "/a", true),
+            ("/a", "a", false),
+            ("a", "b/a", true),
+            ("a", "a/b", true),
+            ("a/", "a", false),
+            ("a", "a/", true),
+            ("a/", "a/b", true),
+            ("*.txt", "a/b.txt", true),
+            ("*.txt", "*.log", false),
+            ("*", "a/b", true),
+            ("a/*", "a/b", true),
+            ("a/*", "a/b/c", true),
+            ("a/*", "b/*", false),
+            ("a/b*", "a/b[1-3]", true),
+            ("a/b[1-3]", "a/b*", false),
+            ("a/b[1-5]", "a/b[2-4]", true),
+            ("a/b?", "a/b[1-3]", true),
+            ("a/b?", "a/b[1-3]c", false),
+            ("a/**/c", "a/b/c", true),
+            ("a/**/c", "a/c", true),
+            ("a/*/c", "a/**/c", false),
+            ("!a", "!a/b", true),
+            ("a", "!a", false),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(
+                subsumes(
+                    &Pattern::new_unchecked(a.to_string()),
+                    &Pattern::new_unchecked(b.to_string())
+                ),
+                expected,
+                "Failed: {:?}, {:?}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn test_differences() {
+        let patterns = vec![
+            Pattern::new_unchecked("a/x.log".to_string()),
+            Pattern::new_unchecked("a/y.log".to_string()),
+        ];
+        let pattern = Pattern::new_unchecked("a/*.log".to_string());
+        let paths = differences(&patterns, &pattern, 3);
+        assert_eq!(paths.len(), 3);
+        assert_eq!(paths[0], "a/.log");
+        for path in paths.iter() {
+            let path = Pattern::from_path(Path::new(path)).anchor();
+            assert!(subsumes(&pattern, &path));
+            assert!(!subsumes_all(&patterns, &path));
+        }
+        let pattern = Pattern::new_unchecked("a/[xy].log".to_string());
+        assert!(differences(&patterns, &pattern, 3).is_empty());
+    }
+
+    #[test]
+    fn test_subsumes_all() {
+        let cases = vec![
+            (vec!["a/b1", "a/b2"], "a/b[12]", true),
+            (vec!["a/b1", "a/b2"], "a/b[1-3]", false),
+            (vec!["a/x1y1", "a/x2y2"], "a/x[12]y[12]", false),
+            (vec!["a/b[1-3]", "a/b[4-6]"], "a/b[1-6]", true),
+            (vec!["a/*.txt", "a/*.log"], "a/*.[lt][ox][gt]", false),
+            (vec!["*.pyc", "*.pyo"], "*.py[co]", true),
+        ];
+        for (patterns, pattern, expected) in cases {
+            let patterns = patterns
+                .iter()
+                .map(|p| Pattern::new_unchecked(p.to_string()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                subsumes_all(&patterns, &Pattern::new_unchecked(pattern.to_string())),
+                expected,
+                "Failed: {:?}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let cases = vec![
+            ("a/b[12]", "!a/b2", true),
+            ("a/b[12]", "!a/b3", false),
+            ("/*2", "a/b[13]", false),
+            ("*2", "a/b[13]", true), // a/b1/x2
+            ("*2", "a/b*", true),
+            ("/a", "b/a", false),
+            ("a/", "a/b", true),
+            ("a/", "!a", true),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(
+                overlaps(
+                    &Pattern::new_unchecked(a.to_string()),
+                    &Pattern::new_unchecked(b.to_string())
+                ),
+                expected,
+                "Failed: {:?}, {:?}",
+                a,
+                b
+            );
+        }
+    }
+}
@@ -1,7 +1,11 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
 
 use fs_tree::FsTree;
 
+use super::matcher::Matcher;
 use super::pattern::{Kind, Pattern};
 
 use super::file::{Content, File, Line};
@@ -9,17 +13,21 @@ use super::file::{Content, File, Line};
 #[derive(Debug, Clone)]
 pub struct DirectoryTree {
     pub root: FsTree,
-    pub globals: HashMap<String, Kind>,
-    pub re_included: HashMap<String, Kind>,
-    pub node_line_map: HashMap<PathBuf, Line>,
+    pub globals: BTreeMap<String, Kind>,
+    pub re_included: BTreeMap<String, Kind>,
+    pub dir_only_globals: BTreeSet<String>, // globals with a trailing '/'
+    pub dir_only_re_included: BTreeSet<String>, // re-included ones with a trailing '/'
+    pub node_line_map: BTreeMap<PathBuf, Line>,
 }
 impl DirectoryTree {
     pub fn new() -> Self {
         Self {
             root: FsTree::new_dir(),
-            globals: HashMap::new(),
-            re_included: HashMap::new(),
-            node_line_map: HashMap::new(),
+            globals: BTreeMap::new(),
+            re_included: BTreeMap::new(),
+            dir_only_globals: BTreeSet::new(),
+            dir_only_re_included: BTreeSet::new(),
+            node_line_map: BTreeMap::new(),
         }
     }
     pub fn build_tree_from_file(f: &File) -> DirectoryTree {
@@ -35,6 +43,12 @@ impl DirectoryTree {
     pub fn add(&mut self, pattern: Pattern, line: Line) {
         match pattern.kind {
             Kind::Global | Kind::Wildcard => {
+                // directory-only unless the same path is also ignored as a file
+                if !pattern.dir_only {
+                    self.dir_only_globals.remove(&pattern.path);
+                } else if !self.globals.contains_key(&pattern.path) {
+                    self.dir_only_globals.insert(pattern.path.clone());
+                }
                 self.globals.insert(pattern.path, pattern.kind);
             }
             Kind::Normal => {
@@ -56,8 +70,33 @@ impl DirectoryTree {
                 if let Kind::Wildcard = *k {
                     panic!("Negation of wildcard is not allowed");
                 }
+                // directory-only unless the same path is also ignored as a file
+                if !pattern.dir_only {
+                    self.dir_only_re_included.remove(&pattern.path);
+                } else if !self.re_included.contains_key(&pattern.path) {
+                    self.dir_only_re_included.insert(pattern.path.clone());
+                }
                 self.re_included.insert(pattern.path, *k);
             }
         }
     }
+    // the lines ignoring a name at any depth (e.g. `*.log` or `logs/`)
+    pub fn globals_matcher(&self) -> Matcher {
+        Matcher::new(
+            &self
+                .globals
+                .keys()
+                .map(|path| {
+                    Pattern::new_unchecked(path.clone())
+                        .with_dir_only(self.dir_only_globals.contains(path))
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+    // whether the line of the node only applies to directories (e.g. `a/b/`)
+    pub fn is_dir_only(&self, path: &Path) -> bool {
+        self.node_line_map
+            .get(path)
+            .is_some_and(|line| line.content.unwrap().ends_with('/'))
+    }
 }
@@ -0,0 +1,143 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// lazy depth-first walk over the entries of a directory (paths relative to the root, sorted)
+// `.git` is never visited, directories for which `prune` returns true are yielded but not
+// descended into, and symlinks are not followed (git stores them as entries)
+pub struct Walker<F> {
+    root: PathBuf,
+    prune: F,
+    stack: Vec<(PathBuf, bool)>,
+}
+impl<F: FnMut(&Path) -> bool> Walker<F> {
+    pub fn new(root: &Path, prune: F) -> Self {
+        // `foo/.gitignore` has an empty parent
+        let root = if root.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            root.to_path_buf()
+        };
+        let mut walker = Self {
+            root,
+            prune,
+            stack: Vec::new(),
+        };
+        walker.push_children(Path::new(""));
+        walker
+    }
+    fn push_children(&mut self, dir: &Path) {
+        let children = read_children(&self.root.join(dir))
+            .into_iter()
+            .map(|(name, is_dir)| (dir.join(name), is_dir))
+            .collect::<Vec<_>>();
+        self.stack.extend(children.into_iter().rev());
+    }
+}
+impl<F: FnMut(&Path) -> bool> Iterator for Walker<F> {
+    type Item = (PathBuf, bool);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, is_dir) = self.stack.pop()?;
+        if is_dir && !(self.prune)(&path) {
+            self.push_children(&path);
+        }
+        Some((path, is_dir))
+    }
+}
+
+// whether the path is a directory, without following symlinks (a symlink to a directory is an
+// entry of its own for git)
+pub fn is_dir(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir())
+}
+
+// whether the path exists and is not a directory (symlinks included)
+pub fn is_file(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| !metadata.is_dir())
+}
+
+// names of the entries of a directory (except `.git`, sorted), with whether they are directories
+// unreadable directories and entries are skipped
+pub fn read_children(dir: &Path) -> Vec<(PathBuf, bool)> {
+    let Ok(rd) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut children = rd
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name() != ".git")
+        .map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            (PathBuf::from(entry.file_name()), is_dir)
+        })
+        .collect::<Vec<_>>();
+    children.sort();
+    children
+}
+
+// directories under the root (the root itself included, as an empty path), without descending
+// into pruned ones
+pub fn walk_dirs(root: &Path, mut prune: impl FnMut(&Path) -> bool) -> Vec<PathBuf> {
+    if prune(Path::new("")) {
+        return vec![PathBuf::new()];
+    }
+    let mut pruned = HashSet::new();
+    let mut dirs = Walker::new(root, |path: &Path| {
+        let prune = prune(path);
+        if prune {
+            pruned.insert(path.to_path_buf());
+        }
+        prune
+    })
+    .filter(|(_, is_dir)| *is_dir)
+    .map(|(path, _)| path)
+    .collect::<Vec<_>>();
+    dirs.retain(|path| !pruned.contains(path));
+    dirs.insert(0, PathBuf::new());
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_walker() {
+        let root = std::env::temp_dir().join(format!("refactorign_walk_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["a/b", "a/c", "node_modules/x", ".git/objects"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["a/b/f", "a/g", "node_modules/x/y", ".git/HEAD"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("a"), root.join("a/c/loop")).unwrap();
+            // sorted before its target, which is still walked
+            std::os::unix::fs::symlink(root.join("a"), root.join("_link")).unwrap();
+        }
+
+        let entries = Walker::new(&root, |path: &Path| path == Path::new("node_modules"))
+            .map(|(path, _)| path.to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let mut expected = vec![];
+        #[cfg(unix)]
+        expected.push("_link");
+        expected.extend(["a", "a/b", "a/b/f", "a/c"]);
+        #[cfg(unix)]
+        expected.push("a/c/loop");
+        expected.extend(["a/g", "node_modules"]);
+        assert_eq!(entries, expected);
+
+        let dirs = walk_dirs(&root, |path: &Path| path == Path::new("a/b"));
+        let mut expected = vec!["", "a", "a/c"];
+        expected.push("node_modules");
+        expected.push("node_modules/x");
+        assert_eq!(
+            dirs.iter().map(|p| p.to_str().unwrap()).collect::<Vec<_>>(),
+            expected
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,20 +1,31 @@
-pub use core::{file, parse, pattern, tree};
+pub use core::{config, file, matcher, parse, pattern, subsume, tree, walk};
 pub use process::refactor::Refactor;
 
 pub mod core {
+    pub mod config;
     pub mod file;
+    pub mod matcher;
     pub mod parse;
     pub mod pattern;
+    pub mod subsume;
     pub mod tree;
+    pub mod walk;
 }
 
 pub mod process {
     pub mod preprocess;
     pub mod containment;
+    pub mod dead;
+    pub mod negation;
     pub mod merge;
     pub mod re_include;
     pub mod postprocess;
+    pub mod expand;
+    pub mod generate;
+    pub mod explain;
+    pub mod lint;
     pub mod refactor;
+    pub mod batch;
     pub mod test;
 }
 
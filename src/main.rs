@@ -1,7 +1,12 @@
 extern crate refactorign;
 
-use clap::Parser;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use refactorign::config::{self, Config};
 use refactorign::parse;
+use refactorign::process::batch;
+use refactorign::process::explain::explain;
+use refactorign::process::refactor::Options;
+use refactorign::walk;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -19,9 +24,31 @@ struct Args {
     #[arg(
         short,
         long,
-        help = "Path to the .gitignore file to refactor (If not provided, the tool will look for a .gitignore file in the current directory)"
+        help = "Path to the .gitignore file to refactor (If not provided, the tool will look for a .gitignore file in the current directory, can be given multiple times)"
     )]
-    path: Option<String>,
+    path: Vec<String>,
+
+    /// Path to a file listing the .gitignore files to refactor
+    #[arg(
+        long,
+        help = "Path to a file listing the .gitignore files to refactor, one per line"
+    )]
+    paths_from: Option<String>,
+
+    /// Number of worker threads used when refactoring multiple files
+    #[arg(
+        short,
+        long,
+        help = "Number of worker threads used when refactoring multiple files (If not provided, the number of available CPUs will be used)"
+    )]
+    jobs: Option<usize>,
+
+    /// Path to the configuration file
+    #[arg(
+        long,
+        help = "Path to the configuration file (If not provided, refactorign.toml next to the .gitignore file or at the root of its repository will be used, the options given here override it)"
+    )]
+    config: Option<String>,
 
     /// Destination path to the directory to place the refactored .gitignore file
     #[arg(
@@ -31,6 +58,13 @@ struct Args {
     )]
     destination: Option<String>,
 
+    /// Name of the refactored .gitignore file
+    #[arg(
+        long,
+        help = "Name of the refactored .gitignore file placed in the destination directory (If not provided, refactored.gitignore will be used)"
+    )]
+    output: Option<String>,
+
     /// Refactoring level (1-3)
     #[arg(
         short,
@@ -41,24 +75,113 @@ struct Args {
     )]
     level: isize,
 
+    /// Whether to apply only the transformations that hold for any directory tree
+    #[arg(
+        short,
+        long,
+        overrides_with = "no_strict",
+        help = "Whether to apply only the transformations that keep the same paths ignored for any directory tree (files created later included)",
+        default_value_t = false
+    )]
+    strict: bool,
+
+    /// Turns off --strict
+    #[arg(
+        long,
+        overrides_with = "strict",
+        help = "Turns off --strict (e.g. when it is set in the configuration file)"
+    )]
+    no_strict: bool,
+
+    /// Whether to remove the lines matching no path in the repository
+    #[arg(
+        long,
+        overrides_with = "no_prune_dead",
+        help = "Whether to remove the lines matching no path in the repository (listed in the report, not done in strict mode)",
+        default_value_t = false
+    )]
+    prune_dead: bool,
+
+    /// Turns off --prune-dead
+    #[arg(
+        long,
+        overrides_with = "prune_dead",
+        help = "Turns off --prune-dead (e.g. when it is set in the configuration file)"
+    )]
+    no_prune_dead: bool,
+
+    /// Path to a file listing every path ever seen in the repository
+    #[arg(
+        long,
+        help = "Path to a file listing every path ever seen in the repository, one per line (e.g. the output of `git log --all --name-only --format=`), with --prune-dead only the lines matching none of them are removed"
+    )]
+    history: Option<String>,
+
+    /// Line to leave untouched
+    #[arg(long, help = "Line to leave untouched (can be given multiple times)")]
+    protect: Vec<String>,
+
+    /// Maximum number of sets of lines the merge process tries for each size
+    #[arg(
+        long,
+        help = "Maximum number of sets of lines the merge process tries for each size (If not provided, 10000 will be used, sizes with more sets are skipped)"
+    )]
+    max_combinations: Option<usize>,
+
     /// Whether to overwrite the original .gitignore file
     #[arg(
         short,
         long,
+        overrides_with = "no_overwrite",
         help = "Whether to overwrite the original .gitignore file",
         default_value_t = false
     )]
     overwrite: bool,
 
+    /// Turns off --overwrite
+    #[arg(
+        long,
+        overrides_with = "overwrite",
+        help = "Turns off --overwrite (e.g. when it is set in the configuration file)"
+    )]
+    no_overwrite: bool,
+
+    /// Whether to keep a backup of the original .gitignore file when overwriting it
+    #[arg(
+        short,
+        long,
+        overrides_with = "no_backup",
+        help = "Whether to keep a backup of the original .gitignore file (<path>.bak) when overwriting it",
+        default_value_t = false
+    )]
+    backup: bool,
+
+    /// Turns off --backup
+    #[arg(
+        long,
+        overrides_with = "backup",
+        help = "Turns off --backup (e.g. when it is set in the configuration file)"
+    )]
+    no_backup: bool,
+
     /// Whether to generate a detailed report on refactoring
     #[arg(
         short,
         long,
+        overrides_with = "no_report",
         help = "Whether to generate a detailed report on refactoring",
         default_value_t = false
     )]
     report: bool,
 
+    /// Turns off --report
+    #[arg(
+        long,
+        overrides_with = "report",
+        help = "Turns off --report (e.g. when it is set in the configuration file)"
+    )]
+    no_report: bool,
+
     /// If set, the tool will run in verbose mode
     #[arg(
         long,
@@ -71,35 +194,109 @@ struct Args {
     /// If set, the tool will just show whether the original .gitignore file is valid
     #[arg(
         long,
+        overrides_with = "no_validate",
         help = "If set, the tool will only show whether the original .gitignore file is valid",
         default_value_t = false
     )]
     validate: bool,
+
+    /// Turns off --validate
+    #[arg(
+        long,
+        overrides_with = "validate",
+        help = "Turns off --validate (e.g. when it is set in the configuration file)"
+    )]
+    no_validate: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Expand the .gitignore file into the explicit list of ignored paths
+    Expand {
+        /// Path to the root directory of the repository
+        #[arg(
+            long,
+            help = "Path to the root directory of the repository (If not provided, the directory of the .gitignore file will be used)"
+        )]
+        root: Option<String>,
+    },
+    /// Generate a minimal .gitignore file from a list of paths to ignore
+    Generate {
+        /// Path to a file listing the paths to ignore
+        #[arg(
+            long,
+            help = "Path to a file listing the paths to ignore, one per line (If not provided, the list will be read from the standard input)"
+        )]
+        from: Option<String>,
+
+        /// Path to the root directory of the repository
+        #[arg(
+            long,
+            help = "Path to the root directory of the repository (If not provided, the current directory will be used)"
+        )]
+        root: Option<String>,
+    },
+    /// Report duplicate, shadowed and ineffective lines without rewriting the .gitignore file
+    Lint,
+    /// Show which lines match a path and which one decides whether it is ignored
+    Explain {
+        /// Path to explain, relative to the directory of the .gitignore file
+        #[arg(
+            help = "Path to explain, relative to the directory of the .gitignore file (a trailing '/' marks a directory)"
+        )]
+        target: String,
+    },
 }
 
-fn validate_args(args: &Args) -> (&Path, PathBuf, bool, u8, bool, bool, u8) {
-    let path = Path::new(args.path.as_deref().unwrap_or("./.gitignore"));
-    if let Some(_) = &args.path {
-        if !path.exists() {
-            eprintln!("Error: The provided path does not exist.");
+fn validate_args(args: &Args) -> (Vec<PathBuf>, Option<PathBuf>, bool, u8, bool, bool, u8) {
+    let mut paths = args.path.iter().map(PathBuf::from).collect::<Vec<_>>();
+    if let Some(list) = &args.paths_from {
+        match std::fs::read_to_string(list) {
+            Ok(content) => paths.extend(
+                content
+                    .lines()
+                    .map(|l| l.trim())
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .map(PathBuf::from),
+            ),
+            Err(_) => {
+                eprintln!("Error: Failed to read the list of paths: {}", list);
+                std::process::exit(1);
+            }
+        }
+    }
+    if paths.is_empty() {
+        if args.paths_from.is_some() {
+            eprintln!("Error: The provided list of paths is empty.");
+            std::process::exit(1);
+        }
+        let path = PathBuf::from("./.gitignore");
+        if !path.exists() && !matches!(args.command, Some(Command::Generate { .. })) {
+            eprintln!("Error: No .gitignore file found in the current directory.");
             std::process::exit(1);
         }
-    } else if !path.exists() {
-        eprintln!("Error: No .gitignore file found in the current directory.");
+        paths.push(path);
+    } else if paths.len() == 1
+        && !paths[0].exists()
+        && !matches!(args.command, Some(Command::Generate { .. }))
+    {
+        eprintln!("Error: The provided path does not exist.");
         std::process::exit(1);
     }
 
-    let destination_default = path.parent().unwrap();
-    let destination = if let Some(d) = args.destination.as_deref() {
-        PathBuf::from(d)
-    } else {
-        destination_default.to_path_buf()
-    };
-    if let Some(_) = &args.destination {
+    let destination = args.destination.as_deref().map(PathBuf::from);
+    if let Some(destination) = &destination {
         if !destination.exists() {
             eprintln!("Error: The provided destination path does not exist.");
             std::process::exit(1);
         }
+        if paths.len() > 1 {
+            eprintln!("Error: The destination path cannot be used with multiple .gitignore files.");
+            std::process::exit(1);
+        }
     }
 
     if args.level > 3 || args.level <= 0 {
@@ -110,15 +307,13 @@ fn validate_args(args: &Args) -> (&Path, PathBuf, bool, u8, bool, bool, u8) {
     }
 
     if args.verbose > 2 || args.verbose < 0 {
-        eprintln!(
-            "Error: Invalid verbose level. The verbose level must be between 0 and 2."
-        );
+        eprintln!("Error: Invalid verbose level. The verbose level must be between 0 and 2.");
         std::process::exit(1);
     }
 
     (
-        path,
-        destination.to_path_buf(),
+        paths,
+        destination,
         args.overwrite,
         args.level as u8,
         args.report,
@@ -129,48 +324,324 @@ fn validate_args(args: &Args) -> (&Path, PathBuf, bool, u8, bool, bool, u8) {
 
 const TEST: bool = true;
 
-fn main() {
-    let args = Args::parse();
-    let (path, destination, overwrite, level, report, validate, verbose) = validate_args(&args);
-    if validate {
-        let content = std::fs::read_to_string(path).unwrap();
-        for line in content
+// fill the options not given on the command line from the configuration file
+fn apply_config(args: &mut Args, matches: &ArgMatches) {
+    let path = match &args.config {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let gitignore = args
+                .path
+                .first()
+                .map(PathBuf::from)
+                .unwrap_or(PathBuf::from("./.gitignore"));
+            match config::find(&gitignore) {
+                Some(path) => path,
+                None => return,
+            }
+        }
+    };
+    let config = Config::load(&path).unwrap_or_else(|e| {
+        eprintln!(
+            "Error: Failed to read the configuration file: {} ({})",
+            path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+    // `--no-<option>` also overrides the file (turning off a flag set in it)
+    let from_cli = |id: &str| {
+        matches.value_source(id) == Some(ValueSource::CommandLine)
+            || matches!(
+                matches.try_get_one::<bool>(&format!("no_{}", id)),
+                Ok(Some(true))
+            )
+    };
+    macro_rules! set {
+        ($($field:ident),*) => {
+            $(
+                if !from_cli(stringify!($field)) {
+                    if let Some(value) = config.$field.clone() {
+                        args.$field = value.into();
+                    }
+                }
+            )*
+        };
+    }
+    set!(
+        path,
+        paths_from,
+        jobs,
+        destination,
+        output,
+        level,
+        strict,
+        prune_dead,
+        history,
+        protect,
+        overwrite,
+        backup,
+        report,
+        verbose,
+        validate
+    );
+    if !from_cli("max_combinations") && config.merge.max_combinations.is_some() {
+        args.max_combinations = config.merge.max_combinations;
+    }
+}
+
+fn options(args: &Args) -> Options {
+    let history = args.history.as_ref().map(|history| {
+        let content = std::fs::read_to_string(history).unwrap_or_else(|_| {
+            eprintln!("Error: Failed to read the list of paths: {}", history);
+            std::process::exit(1);
+        });
+        content
             .lines()
-            .filter(|l| !l.trim().is_empty() && l.chars().next() != Some('#'))
-        {
-            if let Some(_) = parse::parse(line) {
-                continue;
-            } else {
-                println!("Invalid pattern found: {}", line);
+            .map(|l| l.trim().trim_start_matches("./"))
+            .filter(|l| !l.is_empty())
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+    });
+    Options {
+        strict: args.strict,
+        prune_dead: args.prune_dead,
+        history,
+        protected: args.protect.clone(),
+        max_combinations: args.max_combinations,
+    }
+}
+
+fn save_results(
+    result: &Refactor,
+    path: &Path,
+    destination: &Path,
+    file_name: &str,
+    overwrite: bool,
+    backup: bool,
+    report: bool,
+) {
+    let result_path = if overwrite {
+        path.to_path_buf()
+    } else {
+        destination.join(file_name)
+    };
+    if overwrite && backup && path.exists() {
+        match Refactor::backup(path) {
+            Ok(backup_path) => println!("Backed up: {}", backup_path.display()),
+            Err(e) => {
+                eprintln!("Error: Failed to back up {} ({})", path.display(), e);
                 std::process::exit(1);
             }
         }
-        println!("The .gitignore file is valid.");
+    }
+    result.save(result_path.clone());
+    if overwrite {
+        println!("Overwritten: {}", path.display());
+    } else {
+        println!("Saved: {}", destination.display());
+    }
+    if report {
+        result.save_report(
+            destination.join("refactorign_report").as_path(),
+            result_path,
+        );
+    }
+    if TEST {
+        result.save_orig(destination.join("original.gitignore").as_path());
+    }
+}
+
+// print the problems found in the file, and return whether it is valid
+fn validate_file(path: &Path, prefix: &str) -> bool {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        println!("{}Failed to read: {}", prefix, path.display());
+        return false;
+    };
+    let diagnostics = parse::validate(&content);
+    for diagnostic in diagnostics.iter() {
+        println!("{}{}", prefix, diagnostic);
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == parse::Severity::Error)
+        .count();
+    if errors > 0 {
+        println!("{}{} invalid line(s) found.", prefix, errors);
     } else {
-        let result = if verbose != 0 {
-            Refactor::run_verbose(path, level, verbose)
+        println!("{}The .gitignore file is valid.", prefix);
+    }
+    errors == 0
+}
+
+fn run_batch(args: &Args, paths: Vec<PathBuf>, level: u8, overwrite: bool, report: bool) {
+    let jobs = args.jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    let mut results = batch::run_batch(&paths, level, &options(args), jobs);
+    for r in results.iter_mut() {
+        if let Ok(result) = &r.result {
+            let destination = r.path.parent().unwrap().to_path_buf();
+            let result_path = if overwrite {
+                r.path.clone()
+            } else {
+                destination.join(args.output.as_deref().unwrap_or("refactored.gitignore"))
+            };
+            if overwrite && args.backup {
+                if let Err(e) = Refactor::backup(&r.path) {
+                    r.result = Err(format!("Failed to back up: {} ({})", r.path.display(), e));
+                    continue;
+                }
+            }
+            if let Err(e) = result.try_save(&result_path) {
+                r.result = Err(format!(
+                    "Failed to save result to: {} ({})",
+                    result_path.display(),
+                    e
+                ));
+                continue;
+            }
+            if report {
+                result.save_report(
+                    destination.join("refactorign_report").as_path(),
+                    result_path,
+                );
+            }
+            if TEST {
+                result.save_orig(destination.join("original.gitignore").as_path());
+            }
+        }
+    }
+    for line in batch::summary_table(&results) {
+        println!("{}", line);
+    }
+    if results.iter().any(|r| r.result.is_err()) {
+        std::process::exit(1);
+    }
+}
+
+fn main() {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    apply_config(&mut args, &matches);
+    let (paths, destination, overwrite, level, report, validate, verbose) = validate_args(&args);
+    if paths.len() > 1 {
+        if args.command.is_some() {
+            eprintln!("Error: Subcommands cannot be used with multiple .gitignore files.");
+            std::process::exit(1);
+        }
+        if validate {
+            let mut valid = true;
+            for path in paths.iter() {
+                valid &= validate_file(path, &format!("{}: ", path.display()));
+            }
+            if !valid {
+                std::process::exit(1);
+            }
         } else {
-            Refactor::run(path, level)
-        };
-        let result_path = if overwrite {
-            path.to_path_buf()
+            run_batch(&args, paths, level, overwrite, report);
+        }
+        return;
+    }
+    if let Some(Command::Generate { from, root }) = &args.command {
+        let root = PathBuf::from(root.as_deref().unwrap_or("."));
+        if !root.is_dir() {
+            eprintln!("Error: The provided root path does not exist.");
+            std::process::exit(1);
+        }
+        let content = match from {
+            Some(from) => std::fs::read_to_string(from),
+            None => std::io::read_to_string(std::io::stdin()),
+        }
+        .unwrap_or_else(|_| {
+            eprintln!("Error: Failed to read the list of paths to ignore.");
+            std::process::exit(1);
+        });
+        let ignored = content
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>();
+        let result = Refactor::generate(&root, ignored, level, verbose, &options(&args));
+        let path = if args.path.is_empty() {
+            root.join(".gitignore")
         } else {
-            destination.join("refactored.gitignore")
+            paths[0].clone()
         };
-        result.save(result_path.clone());
-        if overwrite {
-            println!("Overwritten: {}", path.display());
-        } else {
-            println!("Saved: {}", destination.display());
+        save_results(
+            &result,
+            &path,
+            &destination.unwrap_or(root),
+            "generated.gitignore",
+            overwrite,
+            args.backup,
+            report,
+        );
+        return;
+    }
+    let path = paths[0].as_path();
+    let destination = destination.unwrap_or(path.parent().unwrap().to_path_buf());
+    // invalid lines are reported before any process runs into them
+    if !validate {
+        if let Err(e) = Refactor::check(path) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
-        if report {
-            result.save_report(
-                destination.join("refactorign_report").as_path(),
-                result_path,
-            );
+    }
+    if let Some(Command::Lint) = &args.command {
+        let mut refactor = Refactor::new(path, level, verbose);
+        let warnings = refactor.set_strict(args.strict).lint();
+        for warning in warnings.iter() {
+            println!("{}: {}", path.display(), warning);
+        }
+        println!("{} warning(s)", warnings.len());
+        return;
+    }
+    if let Some(Command::Explain { target }) = &args.command {
+        let root = path.parent().unwrap();
+        let target = Path::new(target.trim_start_matches("./"));
+        let is_dir = target.to_str().unwrap().ends_with('/') || walk::is_dir(&root.join(target));
+        let result = Refactor::run_with(path, level, 0, &options(&args));
+        println!("Original ({}):", path.display());
+        print!("{}", explain(result.orig_file(), target, is_dir));
+        println!("Refactored:");
+        print!("{}", explain(&result.file(), target, is_dir));
+        return;
+    }
+    if let Some(Command::Expand { root }) = &args.command {
+        let mut refactor = Refactor::new(path, level, verbose);
+        if let Some(root) = root {
+            if !Path::new(root).is_dir() {
+                eprintln!("Error: The provided root path does not exist.");
+                std::process::exit(1);
+            }
+            refactor.state.root = PathBuf::from(root);
         }
-        if TEST {
-            result.save_orig(destination.join("original.gitignore").as_path());
+        let result = refactor.expand();
+        save_results(
+            result,
+            path,
+            &destination,
+            "expanded.gitignore",
+            overwrite,
+            args.backup,
+            false,
+        );
+    } else if validate {
+        if !validate_file(path, "") {
+            std::process::exit(1);
         }
+    } else {
+        let result = Refactor::run_with(path, level, verbose, &options(&args));
+        save_results(
+            &result,
+            path,
+            &destination,
+            args.output.as_deref().unwrap_or("refactored.gitignore"),
+            overwrite,
+            args.backup,
+            report,
+        );
     }
 }
@@ -0,0 +1,148 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use super::refactor::{Options, Refactor};
+
+// processes shown as columns of the summary table (in execution order)
+const PROCESSES: [&str; 7] = [
+    "preprocess",
+    "containment",
+    "dead",
+    "negation",
+    "re_include",
+    "merge",
+    "postprocess",
+];
+
+#[derive(Debug)]
+pub struct BatchResult {
+    pub path: PathBuf,
+    pub result: Result<Refactor, String>,
+}
+
+// run refactoring for each path on a pool of `jobs` workers
+// a failure in one path is recorded in its result and does not stop the others
+pub fn run_batch(paths: &[PathBuf], level: u8, options: &Options, jobs: usize) -> Vec<BatchResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(paths.len()));
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, paths.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= paths.len() {
+                    break;
+                }
+                let result = if paths[i].is_file() {
+                    Refactor::try_run(&paths[i], level, options)
+                } else {
+                    Err("The provided path does not exist.".to_string())
+                };
+                results.lock().unwrap().push((
+                    i,
+                    BatchResult {
+                        path: paths[i].clone(),
+                        result,
+                    },
+                ));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+// one row per path: lines before / after, reduction per process (skipped ones in parentheses), error
+pub fn summary_table(results: &[BatchResult]) -> Vec<String> {
+    let header = ["Path", "Before", "After"]
+        .into_iter()
+        .chain(PROCESSES)
+        .chain(["Error"])
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+    let rows = results
+        .iter()
+        .map(|r| match &r.result {
+            Ok(refactor) => [
+                r.path.display().to_string(),
+                refactor.orig_file().content.len().to_string(),
+                refactor.file().content.len().to_string(),
+            ]
+            .into_iter()
+            .chain(PROCESSES.iter().map(|process| {
+                match refactor.reductions().iter().find(|(p, _, _)| p == process) {
+                    Some((_, n, true)) => format!("({})", n),
+                    Some((_, n, false)) => n.to_string(),
+                    None => "-".to_string(),
+                }
+            }))
+            .chain([String::new()])
+            .collect::<Vec<_>>(),
+            Err(e) => [r.path.display().to_string()]
+                .into_iter()
+                .chain(PROCESSES.iter().chain(&["", ""]).map(|_| "-".to_string()))
+                .chain([e.clone()])
+                .collect::<Vec<_>>(),
+        })
+        .collect::<Vec<_>>();
+    let widths = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([header[i].len()])
+                .max()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let format_row = |row: &Vec<String>| {
+        row.iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+    let mut table = vec![
+        format_row(&header),
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-|-"),
+    ];
+    table.extend(rows.iter().map(format_row));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test;
+    #[test]
+    fn test_batch() {
+        let mut paths = test::get_input_paths("integration")
+            .into_iter()
+            .collect::<Vec<_>>();
+        paths.push(PathBuf::from("tests/data/integration/input/0/gitignore"));
+        let results = run_batch(&paths, 1, &Options::default(), 4);
+        assert_eq!(results.len(), paths.len());
+        for (path, result) in paths.iter().zip(results.iter()) {
+            assert_eq!(*path, result.path);
+        }
+        assert!(results.last().unwrap().result.is_err());
+        assert!(results
+            .iter()
+            .take(paths.len() - 1)
+            .all(|r| r.result.is_ok()));
+        let table = summary_table(&results);
+        assert_eq!(table.len(), paths.len() + 2);
+        assert!(table.last().unwrap().ends_with("does not exist."));
+    }
+}
@@ -1,8 +1,11 @@
 use std::{collections::BTreeMap, path::PathBuf};
 
-use crate::pattern::{does_match, ToString};
+use crate::file::Content;
+use crate::matcher::Matcher;
+use crate::pattern::{Pattern, ToString};
 #[allow(unused_imports)]
 use crate::printv;
+use crate::subsume::subsumes;
 
 use super::refactor::Refactor;
 
@@ -14,22 +17,40 @@ impl Refactor {
         }
 
         let line_num = file.content.len();
-        for node in tree.root.paths().min_depth(1) {
+        if self.strict() {
+            self.strict_containment(verbose);
+            self.finish(false, "containment", line_num);
+            return self;
+        }
+        // the nodes are lines, matched against each other as paths
+        let nodes = tree.root.paths().min_depth(1).collect::<Vec<_>>();
+        let matcher = Matcher::new(
+            &nodes
+                .iter()
+                .map(|node| Pattern::new_unchecked(node.to_string()).anchor())
+                .collect::<Vec<_>>(),
+        );
+        let matched = nodes
+            .iter()
+            .map(|path| matcher.matches(path, true))
+            .collect::<Vec<_>>();
+        let globals = tree.globals_matcher();
+        for (k, node) in nodes.iter().enumerate() {
             // global containment (wildcard / global)
-            if self.is_globally_ignored(&node) {
-                if let Some(line) = tree.node_line_map.get(&node) {
+            if self.is_globally_ignored(&globals, node) {
+                if let Some(line) = tree.node_line_map.get(node) {
                     let file = self.file_mut();
                     file.remove_line_with_path(PathBuf::from(line.content.unwrap()), verbose);
                 }
             }
             // normal containment (directory-structure)
-            if self.is_normally_ignored(&node) {
+            if self.is_normally_ignored(node) {
                 // let children = tree.root.get(&parent).unwrap().children().unwrap();
-                let childrens = tree
-                    .root
-                    .paths()
-                    .filter(|path| does_match(path, &node.to_string()))
-                    .map(|path| {
+                let childrens = nodes
+                    .iter()
+                    .zip(matched.iter())
+                    .filter(|(_, matched)| matched.contains(&k))
+                    .map(|(path, _)| {
                         (
                             path.clone(),
                             tree.root.get(path).unwrap().children().unwrap().clone(),
@@ -58,6 +79,40 @@ impl Refactor {
         self.finish(false, "containment", line_num);
         self
     }
+
+    // remove lines whose paths are ignored by another line anyway, whatever the tree is
+    fn strict_containment(&mut self, verbose: u8) {
+        'outer: loop {
+            let patterns = self
+                .file()
+                .content
+                .iter()
+                .enumerate()
+                .filter_map(|(i, line)| match &line.content {
+                    Content::Pattern(p) => Some((i, Pattern::new_unchecked(p.to_string()))),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for (k, (i, b)) in patterns.iter().enumerate() {
+                // a protected line stays even if another line covers it
+                if b.negated || self.file().is_protected(&self.file().content[*i]) {
+                    continue;
+                }
+                let contained = patterns.iter().enumerate().any(|(l, (_, a))| {
+                    l != k
+                        && !a.negated
+                        // a negation between the lines may re-include some of the paths
+                        && (l > k || !patterns[l..k].iter().any(|(_, p)| p.negated))
+                        && subsumes(a, b)
+                });
+                if contained {
+                    self.file_mut().remove_line_with_index(*i, verbose);
+                    continue 'outer;
+                }
+            }
+            break;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,153 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{file::Content, matcher::Matcher, pattern::Pattern, printv, walk::Walker};
+
+use super::refactor::Refactor;
+
+// indices of the patterns matching a path of the tree
+// excluded directories are walked into too, as a line may only match paths inside of one
+// (e.g. `*.o` with `build/` and `build/x.o`)
+pub fn matched_in_tree(root: &Path, matcher: &Matcher) -> BTreeSet<usize> {
+    Walker::new(root, |_| false)
+        .flat_map(|(path, is_dir): (PathBuf, bool)| matcher.matches(&path, is_dir))
+        .collect()
+}
+
+// indices of the patterns matching one of the paths (files) or one of their directories
+fn matched_in_history(history: &[PathBuf], matcher: &Matcher) -> BTreeSet<usize> {
+    let mut matched = BTreeSet::new();
+    for path in history {
+        matched.extend(matcher.matches(path, false));
+        for dir in path.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            matched.extend(matcher.matches(dir, true));
+        }
+    }
+    matched
+}
+
+impl Refactor {
+    // remove the lines matching no path of the tree (nor of the history, if given)
+    // only when asked to, and never in strict mode
+    pub fn dead(&mut self) -> &mut Self {
+        if !self.state.prune_dead || self.strict() {
+            return self;
+        }
+        let (verbose, root, tree, file) = self.get_borrows();
+        if verbose == 2 {
+            printv!(root, tree, file);
+        }
+
+        let line_num = file.content.len();
+        let (lines, protected): (Vec<_>, Vec<_>) = file
+            .content
+            .iter()
+            .filter_map(|line| match &line.content {
+                Content::Pattern(p) => Some((p.clone(), file.is_protected(line))),
+                _ => None,
+            })
+            .unzip();
+        let matcher = Matcher::new(
+            &lines
+                .iter()
+                .map(|line| Pattern::new_unchecked(line.clone()))
+                .collect::<Vec<_>>(),
+        );
+        let mut matched = matched_in_tree(&root, &matcher);
+        if let Some(history) = &self.state.history {
+            matched.extend(matched_in_history(history, &matcher));
+        }
+        let dead = lines
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !matched.contains(i) && !protected[*i])
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>();
+        for line in dead.iter() {
+            self.file_mut().remove_line(line.clone(), verbose);
+        }
+        // the next processes remove lines through the tree
+        self.rebuild_tree();
+        self.write_report(
+            dead.iter()
+                .map(|line| format!("Dead line removed: {}", line))
+                .collect(),
+        );
+        self.finish(false, "dead", line_num);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        process::{refactor::Options, test},
+        show_result,
+    };
+    #[test]
+    fn test_dead() {
+        for level in 1..=1 {
+            for path in test::get_input_paths("dead") {
+                test::show_title(&path, level);
+                let refactor = &mut Refactor::new(&path, level, 2);
+                let result = refactor
+                    .set_prune_dead(true)
+                    .protect(&["/secrets.env".to_string()])
+                    .preprocess()
+                    .dead()
+                    .postprocess();
+                show_result!(&result.file());
+                assert!(test::file_cmp(
+                    result.file(),
+                    test::get_expected_path(&path, level)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let path = PathBuf::from("tests/data/dead/input/1/gitignore");
+        let refactor = &mut Refactor::new(&path, 1, 0);
+        let result = refactor
+            .set_prune_dead(true)
+            .set_history(Some(vec![PathBuf::from("old/build/out.o")]))
+            .preprocess()
+            .dead()
+            .postprocess();
+        let lines = result
+            .file()
+            .content
+            .iter()
+            .map(|line| line.content.unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert!(lines.contains(&"old/".to_string()));
+        assert!(lines.contains(&"*.o".to_string()));
+        assert!(!lines.contains(&"/missing".to_string()));
+    }
+
+    #[test]
+    fn test_dead_run() {
+        // the tree follows the removed lines (e.g. `a/b/gone` under a collapsed directory)
+        let path = PathBuf::from("tests/data/dead/input/3/gitignore");
+        let options = Options {
+            prune_dead: true,
+            ..Options::default()
+        };
+        let result = Refactor::run_with(&path, 1, 0, &options);
+        let lines = result
+            .file()
+            .content
+            .iter()
+            .map(|line| line.content.unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert!(lines.contains(&"*.o".to_string()));
+        assert!(!lines.iter().any(|line| line.contains("gone")));
+    }
+}
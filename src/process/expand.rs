@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    file::{Content, Line},
+    matcher::Matcher,
+    pattern::Pattern,
+    walk::Walker,
+};
+#[allow(unused_imports)]
+use crate::{printv, tree::DirectoryTree};
+
+use super::refactor::Refactor;
+
+// collect ignored paths under the root
+// a directory that is ignored as a whole is listed once, without descending into it
+fn get_ignored_paths(matcher: &Matcher, root: &Path) -> Vec<(PathBuf, bool)> {
+    Walker::new(root, |dir| matcher.is_ignored(dir, true))
+        .filter(|(path, is_dir)| matcher.is_ignored(path, *is_dir))
+        .collect()
+}
+
+impl Refactor {
+    // rewrite the file into the explicit list of the paths it ignores in the tree
+    pub fn expand(&mut self) -> &mut Self {
+        let (verbose, root, tree, file) = self.get_borrows();
+        if verbose == 2 {
+            printv!(root, tree, file);
+        }
+
+        let patterns = file
+            .content
+            .iter()
+            .filter_map(|line| match &line.content {
+                Content::Pattern(p) => Some(Pattern::new_unchecked(p.to_string())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // (content, origin)
+        let mut content = get_ignored_paths(&Matcher::new(&patterns), &root)
+            .into_iter()
+            .map(|(path, is_dir)| {
+                let line = Pattern::from_path(&path)
+                    .anchor()
+                    .with_dir_only(is_dir)
+                    .to_string();
+                (Content::Pattern(line), None)
+            })
+            .collect::<Vec<_>>();
+        // protected lines are kept as they are (still protected), after the list
+        let protected = file
+            .content
+            .iter()
+            .filter(|line| file.is_protected(line))
+            .map(|line| (line.content.clone(), line.origin))
+            .collect::<Vec<_>>();
+        if !protected.is_empty() {
+            content.push((Content::Comment("# refactorign: off".to_string()), None));
+            content.extend(protected);
+            content.push((Content::Comment("# refactorign: on".to_string()), None));
+        }
+        let content = content
+            .into_iter()
+            .enumerate()
+            .map(|(i, (content, origin))| Line {
+                content,
+                line_number: i + 1,
+                origin,
+            })
+            .collect::<Vec<_>>();
+        if verbose > 0 {
+            for line in content.iter() {
+                println!("Expanded: {}\r\n", line.content.unwrap());
+            }
+        }
+        self.file_mut().content = content;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process::test, show_result};
+    #[test]
+    fn test_expand() {
+        for level in 1..=1 {
+            for path in test::get_input_paths("expand") {
+                test::show_title(&path, level);
+                let refactor = &mut Refactor::new(&path, level, 2);
+                let result = refactor.expand();
+                show_result!(&result.file());
+                assert!(test::file_cmp(
+                    result.file(),
+                    test::get_expected_path(&path, level)
+                ));
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expand_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        let root = std::env::temp_dir().join(format!("refactorign_utf8_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::write(
+            root.join("a")
+                .join(std::ffi::OsStr::from_bytes(b"caf\xe9.log")),
+            "",
+        )
+        .unwrap();
+        std::fs::write(root.join("a/thé.log"), "").unwrap();
+        std::fs::write(root.join("a/keep"), "").unwrap();
+        std::fs::write(root.join(".gitignore"), "a/*.log\n").unwrap();
+        let result = Refactor::new(&root.join(".gitignore"), 1, 0)
+            .expand()
+            .file();
+        let lines = result
+            .content
+            .iter()
+            .map(|line| line.content.unwrap())
+            .collect::<Vec<_>>();
+        // the bytes that are not UTF-8 are written as a wildcard
+        assert_eq!(lines, vec!["/a/caf*.log", "/a/thé.log"]);
+        // and refactoring does not panic on them nor change the ignored paths
+        std::fs::write(root.join(".gitignore"), "*.log\na/keep\n").unwrap();
+        let expected = Refactor::new(&root.join(".gitignore"), 1, 0)
+            .expand()
+            .file();
+        let mut result = Refactor::run(&root.join(".gitignore"), 2);
+        assert_eq!(result.expand().file().content, expected.content);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_expand_refactored() {
+        // refactoring should not change the set of ignored paths
+        for level in 1..=1 {
+            for path in test::get_input_paths("integration") {
+                test::show_title(&path, level);
+                let expected = Refactor::new(&path, level, 0).expand().file();
+                let mut result = Refactor::run(&path, level);
+                show_result!(&result.file());
+                assert_eq!(
+                    result.expand().file().content,
+                    expected.content,
+                    "Failed: {:?}",
+                    path
+                );
+            }
+        }
+    }
+}
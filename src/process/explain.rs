@@ -0,0 +1,171 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    file::{Content, File},
+    matcher::Matcher,
+    pattern::Pattern,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub line_number: usize,
+    pub line: String,
+    pub negated: bool,
+}
+
+// every line matching a path, in order, and the one deciding whether it is ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub matches: Vec<Match>,
+    // git does not look into an excluded directory, whatever the lines matching its contents
+    pub excluded_parent: Option<(PathBuf, Match)>,
+}
+impl Explanation {
+    pub fn deciding(&self) -> Option<&Match> {
+        match &self.excluded_parent {
+            Some((_, m)) => Some(m),
+            None => self.matches.last(),
+        }
+    }
+    pub fn is_ignored(&self) -> bool {
+        self.deciding().is_some_and(|m| !m.negated)
+    }
+}
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{}{}: {}",
+            self.path.display(),
+            if self.is_dir { "/" } else { "" },
+            if self.is_ignored() {
+                "ignored"
+            } else {
+                "not ignored"
+            }
+        )?;
+        if let Some((parent, m)) = &self.excluded_parent {
+            writeln!(
+                f,
+                "    line {}: {} (decides: excludes the parent directory {}/)",
+                m.line_number,
+                m.line,
+                parent.display()
+            )?;
+        }
+        if self.matches.is_empty() && self.excluded_parent.is_none() {
+            writeln!(f, "    (no line matches)")?;
+        }
+        for (i, m) in self.matches.iter().enumerate() {
+            let decides = self.excluded_parent.is_none() && i == self.matches.len() - 1;
+            writeln!(
+                f,
+                "    line {}: {}{}",
+                m.line_number,
+                m.line,
+                if decides { " (decides)" } else { "" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// explain the status of a path (relative to the root of the file) with the lines of the file
+pub fn explain(file: &File, path: &Path, is_dir: bool) -> Explanation {
+    let (line_numbers, lines): (Vec<_>, Vec<_>) = file
+        .content
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| match &line.content {
+            Content::Pattern(p) => Some((i + 1, p.clone())),
+            _ => None,
+        })
+        .unzip();
+    let matcher = Matcher::new(
+        &lines
+            .iter()
+            .map(|line| Pattern::new_unchecked(line.clone()))
+            .collect::<Vec<_>>(),
+    );
+    let to_match = |i: usize| Match {
+        line_number: line_numbers[i],
+        line: lines[i].clone(),
+        negated: matcher.patterns()[i].negated,
+    };
+    let path = PathBuf::from(path.to_str().unwrap_or_default().trim_matches('/'));
+    let excluded_parent = path
+        .ancestors()
+        .skip(1)
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .find_map(|parent| {
+            let last = *matcher.matches(parent, true).last()?;
+            (!matcher.patterns()[last].negated).then(|| (parent.to_path_buf(), to_match(last)))
+        });
+    Explanation {
+        matches: matcher
+            .matches(&path, is_dir)
+            .into_iter()
+            .map(to_match)
+            .collect(),
+        path,
+        is_dir,
+        excluded_parent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_explain() {
+        let file = File::from_lines(
+            PathBuf::from("gitignore"),
+            ["*.log", "/build/", "!keep.log", "a/*", "!a/b"]
+                .iter()
+                .map(|l| l.to_string())
+                .collect(),
+        );
+        let cases = vec![
+            // (path, is_dir, matching lines, excluded parent, ignored)
+            ("x.log", false, vec![1], None, true),
+            ("keep.log", false, vec![1, 3], None, false),
+            ("build", true, vec![2], None, true),
+            ("build", false, vec![], None, false),
+            ("build/keep.log", false, vec![1, 3], Some(2), true),
+            ("a/c", false, vec![4], None, true),
+            ("a/b", true, vec![4, 5], None, false),
+            ("a/b/c", false, vec![], None, false),
+            ("src/main.rs", false, vec![], None, false),
+        ];
+        for (path, is_dir, matches, parent, ignored) in cases {
+            let explanation = explain(&file, Path::new(path), is_dir);
+            println!("{}", explanation);
+            assert_eq!(
+                explanation
+                    .matches
+                    .iter()
+                    .map(|m| m.line_number)
+                    .collect::<Vec<_>>(),
+                matches,
+                "Failed: {:?}",
+                path
+            );
+            assert_eq!(
+                explanation
+                    .excluded_parent
+                    .as_ref()
+                    .map(|(_, m)| m.line_number),
+                parent
+            );
+            assert_eq!(explanation.is_ignored(), ignored, "Failed: {:?}", path);
+        }
+    }
+}
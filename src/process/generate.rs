@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+use crate::{file::File, pattern::Pattern};
+
+use super::refactor::{Options, Refactor};
+
+// turn a path to ignore into a line matching only that path
+fn to_line(path: &str) -> Option<String> {
+    let path = path.trim();
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let path = path.trim_start_matches('/').trim_end_matches('/');
+    if path.is_empty() {
+        None
+    } else {
+        Some(
+            Pattern::from_path(Path::new(path))
+                .anchor_top_level()
+                .to_string(),
+        )
+    }
+}
+
+impl Refactor {
+    // synthesize a .gitignore file ignoring the given paths (relative to the root)
+    // the explicit list is compressed by the same processes (and options) as refactoring
+    pub fn generate(
+        root: &Path,
+        paths: Vec<String>,
+        level: u8,
+        verbose: u8,
+        options: &Options,
+    ) -> Refactor {
+        let lines = paths
+            .iter()
+            .filter_map(|path| to_line(path))
+            .collect::<Vec<_>>();
+        if verbose > 0 {
+            for path in lines
+                .iter()
+                .filter(|l| !root.join(l.trim_start_matches('/')).exists())
+            {
+                println!("Not found in the tree: {}\r\n", path);
+            }
+        }
+        let file = File::from_lines(PathBuf::from(root).join(".gitignore"), lines);
+        let refactor = &mut Refactor::from_file(file, root.to_path_buf(), level, verbose);
+        refactor
+            .configure(options)
+            .preprocess()
+            .containment()
+            .dead()
+            .negation()
+            .re_include()
+            .merge()
+            .postprocess()
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process::test, show_result};
+    use std::fs;
+
+    // each input "gitignore" lists the paths to ignore, one per line
+    #[test]
+    fn test_generate() {
+        for level in 1..=1 {
+            for path in test::get_input_paths("generate") {
+                test::show_title(&path, level);
+                let paths = fs::read_to_string(&path)
+                    .unwrap()
+                    .lines()
+                    .map(|l| l.to_string())
+                    .collect::<Vec<_>>();
+                let mut result = Refactor::generate(
+                    path.parent().unwrap(),
+                    paths.clone(),
+                    level,
+                    2,
+                    &Options::default(),
+                );
+                show_result!(&result.file());
+                assert!(test::file_cmp(
+                    result.file(),
+                    test::get_expected_path(&path, level)
+                ));
+                // the generated file ignores exactly the given paths
+                let mut expanded = result
+                    .expand()
+                    .file()
+                    .content
+                    .iter()
+                    .map(|l| to_line(l.content.unwrap()).unwrap())
+                    .collect::<Vec<_>>();
+                let mut expected = paths.iter().filter_map(|p| to_line(p)).collect::<Vec<_>>();
+                expanded.sort();
+                expected.sort();
+                assert_eq!(expanded, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_options() {
+        let path = PathBuf::from("tests/data/generate/input/1/gitignore");
+        let paths = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>();
+        let options = Options {
+            strict: true,
+            protected: vec!["b/1.log".to_string()],
+            ..Options::default()
+        };
+        let result = Refactor::generate(path.parent().unwrap(), paths, 1, 0, &options);
+        assert!(result.strict());
+        let lines = result
+            .file()
+            .content
+            .iter()
+            .map(|line| line.content.unwrap().to_string())
+            .collect::<Vec<_>>();
+        // no wildcard from the tree in strict mode, and the protected line is left as is
+        assert!(!lines.contains(&"b/*.log".to_string()));
+        assert!(lines.contains(&"b/1.log".to_string()));
+    }
+
+    #[test]
+    fn test_to_line() {
+        assert_eq!(to_line("a"), Some("/a".to_string()));
+        assert_eq!(to_line("./a/b/"), Some("a/b".to_string()));
+        assert_eq!(to_line("/a/b"), Some("a/b".to_string()));
+        assert_eq!(to_line(" "), None);
+    }
+}
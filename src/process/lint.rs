@@ -0,0 +1,226 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+use crate::{
+    file::Content,
+    matcher::Matcher,
+    pattern::{Pattern, Token},
+    subsume::subsumes,
+};
+
+use super::{dead::matched_in_tree, refactor::Refactor};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    Duplicate(usize),        // line number of the first occurrence
+    Shadowed(Option<usize>), // line number of the rule shadowing it (if a single one does)
+    Dead,
+    IneffectiveNegation(String), // excluded parent directory
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub line_number: usize,
+    pub line: String,
+    pub kind: WarningKind,
+}
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match &self.kind {
+            WarningKind::Duplicate(first) => format!("duplicate of line {}", first),
+            WarningKind::Shadowed(Some(by)) => format!("shadowed by line {}", by),
+            WarningKind::Shadowed(None) => "shadowed by other rules".to_string(),
+            WarningKind::Dead => "matches nothing in the tree".to_string(),
+            WarningKind::IneffectiveNegation(parent) => format!(
+                "cannot take effect because the parent directory {}/ is excluded",
+                parent
+            ),
+        };
+        write!(f, "line {}: {}: {}", self.line_number, self.line, message)
+    }
+}
+
+// directories the paths matched by a negation are in, as far as they are written literally
+// e.g. `!a/b/*.txt` -> [a, a/b]
+pub fn literal_parents(pattern: &Pattern) -> Vec<String> {
+    if !pattern.anchored && pattern.segments.len() == 1 {
+        return Vec::new();
+    }
+    let mut parents = Vec::new();
+    let mut current = String::new();
+    for segment in pattern.segments[..pattern.segments.len() - 1].iter() {
+        if !segment
+            .iter()
+            .all(|token| matches!(token, Token::Char(_) | Token::Escaped(_)))
+        {
+            break;
+        }
+        if !current.is_empty() {
+            current.push('/');
+        }
+        current.extend(segment.iter().flat_map(|token| token.chars()).flatten());
+        parents.push(current.clone());
+    }
+    parents
+}
+
+impl Refactor {
+    // report duplicate, shadowed, dead and ineffective lines without rewriting the file
+    pub fn lint(&self) -> Vec<Warning> {
+        let lines = self
+            .orig_file()
+            .content
+            .iter()
+            .filter_map(|line| match &line.content {
+                Content::Pattern(p) => Some((line.line_number, p.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut warnings = Vec::new();
+
+        // duplicates (what preprocess removes)
+        let mut first = HashMap::new();
+        let mut duplicates = HashSet::new();
+        for (line_number, line) in lines.iter() {
+            match first.get(line) {
+                Some(first) => {
+                    duplicates.insert(*line_number);
+                    warnings.push(Warning {
+                        line_number: *line_number,
+                        line: line.clone(),
+                        kind: WarningKind::Duplicate(*first),
+                    });
+                }
+                None => {
+                    first.insert(line.clone(), *line_number);
+                }
+            }
+        }
+
+        // shadowed lines (what containment removes)
+        let remaining = Refactor::from_file(
+            self.orig_file().clone(),
+            self.root().clone(),
+            self.level(),
+            0,
+        )
+        .set_strict(self.strict())
+        .preprocess()
+        .containment()
+        .file()
+        .content
+        .iter()
+        .filter_map(|line| match &line.content {
+            Content::Pattern(p) => Some(p.clone()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+        let patterns = lines
+            .iter()
+            .map(|(_, line)| Pattern::new_unchecked(line.clone()))
+            .collect::<Vec<_>>();
+        let mut shadowed = HashSet::new();
+        for (k, (line_number, line)) in lines.iter().enumerate() {
+            if duplicates.contains(line_number) || remaining.contains(line) {
+                continue;
+            }
+            shadowed.insert(*line_number);
+            let by = lines.iter().enumerate().find(|(l, (n, other))| {
+                *l != k
+                    && !duplicates.contains(n)
+                    && remaining.contains(other)
+                    && !patterns[*l].negated
+                    && subsumes(&patterns[*l], &patterns[k])
+            });
+            warnings.push(Warning {
+                line_number: *line_number,
+                line: line.clone(),
+                kind: WarningKind::Shadowed(by.map(|(_, (n, _))| *n)),
+            });
+        }
+
+        // negations under an excluded directory (git does not look into it)
+        let matcher = Matcher::new(&patterns);
+        let mut ineffective = HashSet::new();
+        for ((line_number, line), pattern) in lines.iter().zip(patterns.iter()) {
+            if !pattern.negated || duplicates.contains(line_number) {
+                continue;
+            }
+            if let Some(parent) = literal_parents(pattern)
+                .into_iter()
+                .find(|parent| matcher.is_ignored(Path::new(parent), true))
+            {
+                ineffective.insert(*line_number);
+                warnings.push(Warning {
+                    line_number: *line_number,
+                    line: line.clone(),
+                    kind: WarningKind::IneffectiveNegation(parent),
+                });
+            }
+        }
+
+        // dead lines (matching no path of the tree outside of excluded directories)
+        let matched = matched_in_tree(self.root(), &matcher);
+        for (i, (line_number, line)) in lines.iter().enumerate() {
+            if matched.contains(&i)
+                || duplicates.contains(line_number)
+                || shadowed.contains(line_number)
+                || ineffective.contains(line_number)
+            {
+                continue;
+            }
+            warnings.push(Warning {
+                line_number: *line_number,
+                line: line.clone(),
+                kind: WarningKind::Dead,
+            });
+        }
+
+        warnings.sort_by_key(|warning| warning.line_number);
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test;
+    #[test]
+    fn test_literal_parents() {
+        let cases = vec![
+            ("!a", vec![]),
+            ("!/a", vec![]),
+            ("!a/b", vec!["a"]),
+            ("!a/b/*.txt", vec!["a", "a/b"]),
+            ("!a/*/c", vec!["a"]),
+        ];
+        for (pat, expected) in cases {
+            assert_eq!(
+                literal_parents(&Pattern::new_unchecked(pat.to_string())),
+                expected,
+                "Failed: {:?}",
+                pat
+            );
+        }
+    }
+
+    #[test]
+    fn test_lint() {
+        for path in test::get_input_paths("lint") {
+            test::show_title(&path, 1);
+            let warnings = Refactor::new(&path, 1, 0)
+                .lint()
+                .iter()
+                .map(|warning| warning.to_string())
+                .collect::<Vec<_>>();
+            for warning in warnings.iter() {
+                println!("{}", warning);
+            }
+            let expected = std::fs::read_to_string(test::get_expected_path(&path, 1)).unwrap();
+            assert_eq!(warnings, expected.lines().collect::<Vec<_>>());
+        }
+    }
+}
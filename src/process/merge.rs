@@ -1,16 +1,25 @@
-use std::{iter, ops::Range, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 #[allow(unused_imports)]
 use crate::{
-    file::Content,
-    pattern::{does_match, expand_range, ToString},
+    file::{Content, File},
+    matcher::Matcher,
+    pattern::{ClassItem, Pattern, ToString, Token},
     printv,
+    subsume::{differences, overlaps, subsumes_all},
+    tree::DirectoryTree,
+    walk::{read_children, Walker},
 };
 
 use super::refactor::Refactor;
-use fs_tree::FsTree;
 use itertools::Itertools;
-use regex::Regex;
+
+// sets of lines tried at most for each size, unless configured otherwise
+pub const MAX_COMBINATIONS: usize = 10000;
 
 // get wildcard-able line diff
 // consider only the last part of the line
@@ -87,7 +96,8 @@ fn line_diff_string(
         for (range, chars) in result_indices.iter().zip(set_raw) {
             let mut tmp = chars.split("/").collect::<Vec<_>>();
             tmp.pop();
-            let offset = tmp.join("/").len() + (if tmp.len() > 0 { 1 } else { 0 });
+            // character indices, as the ranges
+            let offset = tmp.join("/").chars().count() + (if tmp.len() > 0 { 1 } else { 0 });
             let left = offset + range.start;
             let right = offset + range.end;
             prefix_ranges.push(if left <= offset {
@@ -95,19 +105,157 @@ fn line_diff_string(
             } else {
                 Some(offset..left)
             });
-            suffix_ranges.push(if right >= chars.len() {
-                None
-            } else {
-                Some(right..chars.len())
-            })
+            let len = chars.chars().count();
+            suffix_ranges.push(if right >= len { None } else { Some(right..len) })
         }
         Some((prefix_ranges, suffix_ranges))
     }
 }
 
+// characters splitting a name into words (e.g. test_output_a, tmp-1234, app.min.js)
+const DELIMITERS: [char; 3] = ['_', '-', '.'];
+// shortest common prefix or suffix (delimiter included) worth keeping in a merged line
+const MIN_AFFIX_LEN: usize = 3;
+
+// get the parts of the last parts of the lines between their common prefix and suffix
+// the prefix should end with a delimiter and the suffix should start with one
+// return the (character) ranges of the parts in the lines
+fn line_diff_affix(set_raw: &[String]) -> Option<Vec<Range<usize>>> {
+    let names = set_raw
+        .iter()
+        .map(|line| {
+            let line = line.strip_suffix('/').unwrap_or(line);
+            let name = line.rsplit('/').next().unwrap();
+            let offset = line.chars().count() - name.chars().count();
+            (offset, name.chars().collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+    let first = &names[0].1;
+    let shortest = names.iter().map(|(_, name)| name.len()).min()?;
+
+    let mut prefix = (0..shortest)
+        .take_while(|i| names.iter().all(|(_, name)| name[*i] == first[*i]))
+        .count();
+    // cut after the last delimiter
+    prefix = first[..prefix]
+        .iter()
+        .rposition(|c| DELIMITERS.contains(c))
+        .map_or(0, |i| i + 1);
+    if prefix < MIN_AFFIX_LEN {
+        prefix = 0;
+    }
+    let mut suffix = (0..shortest - prefix)
+        .take_while(|i| {
+            let c = first[first.len() - 1 - i];
+            names.iter().all(|(_, name)| name[name.len() - 1 - i] == c)
+        })
+        .count();
+    // cut before the first delimiter
+    suffix = first[first.len() - suffix..]
+        .iter()
+        .position(|c| DELIMITERS.contains(c))
+        .map_or(0, |i| suffix - i);
+    if suffix < MIN_AFFIX_LEN {
+        suffix = 0;
+    }
+    if prefix == 0 && suffix == 0 {
+        return None;
+    }
+    Some(
+        names
+            .iter()
+            .map(|(offset, name)| offset + prefix..offset + name.len() - suffix)
+            .collect(),
+    )
+}
+
+// split a line into the part before the last number of its name, the number and the part after
+// (e.g. a/log12.txt -> (a/log, 12, .txt))
+fn split_number(line: &str) -> Option<(String, String, String)> {
+    let chars = line.chars().collect::<Vec<_>>();
+    let name_start = chars.iter().rposition(|c| *c == '/').map_or(0, |i| i + 1);
+    // the trailing slash of a directory-only line
+    let name_start = if name_start == chars.len() {
+        chars[..name_start - 1]
+            .iter()
+            .rposition(|c| *c == '/')
+            .map_or(0, |i| i + 1)
+    } else {
+        name_start
+    };
+    let end = name_start
+        + chars[name_start..]
+            .iter()
+            .rposition(|c| c.is_ascii_digit())?
+        + 1;
+    let start = chars[name_start..end]
+        .iter()
+        .rposition(|c| !c.is_ascii_digit())
+        .map_or(name_start, |i| name_start + i + 1);
+    Some((
+        chars[..start].iter().collect(),
+        chars[start..end].iter().collect(),
+        chars[end..].iter().collect(),
+    ))
+}
+
+// sequences of tokens matching exactly the numbers (digit strings of the same length)
+// (e.g. 10, 11, ..., 25 -> 1[0-9], 2[0-5])
+fn number_classes(numbers: &BTreeSet<String>) -> Vec<Vec<Token>> {
+    if numbers.iter().all(|number| number.is_empty()) {
+        return vec![vec![]];
+    }
+    // the rest of the numbers for each first digit
+    let mut rests = BTreeMap::<char, BTreeSet<String>>::new();
+    for number in numbers {
+        let mut chars = number.chars();
+        let first = chars.next().unwrap();
+        rests.entry(first).or_default().insert(chars.collect());
+    }
+    // the first digits sharing the same rests become a class
+    let mut digits = BTreeMap::<BTreeSet<String>, Vec<char>>::new();
+    for (first, rest) in rests {
+        digits.entry(rest).or_default().push(first);
+    }
+    digits
+        .into_iter()
+        .sorted_by_key(|(_, firsts)| firsts[0])
+        .flat_map(|(rest, firsts)| {
+            let first = if firsts.len() == 1 {
+                Token::Char(firsts[0])
+            } else {
+                to_class(firsts)
+            };
+            number_classes(&rest)
+                .into_iter()
+                .map(move |tokens| std::iter::once(first.clone()).chain(tokens).collect())
+        })
+        .collect()
+}
+
+// lines matching exactly the lines differing only in their numbers
+// (e.g. log1, log2, ..., log25 -> log[1-9], log1[0-9], log2[0-5])
+fn number_lines(before: &str, numbers: &BTreeSet<String>, after: &str) -> Vec<Pattern> {
+    numbers
+        .iter()
+        .into_group_map_by(|number| number.chars().count())
+        .into_iter()
+        .sorted_by_key(|(len, _)| *len)
+        .flat_map(|(_, numbers)| number_classes(&numbers.into_iter().cloned().collect()))
+        .map(|tokens| {
+            Pattern::new_unchecked(format!(
+                "{}{}{}",
+                before,
+                tokens.iter().map(|t| t.to_string()).join(""),
+                after
+            ))
+        })
+        .collect()
+}
+
 // get the difference between all of the lines (character-wise)
 fn line_diff_char(set: Vec<String>) -> Option<Vec<usize>> {
-    if !set.iter().map(|line| line.len()).all_equal() || set.len() < 2 {
+    if !set.iter().map(|line| line.chars().count()).all_equal() || set.len() < 2 {
         return None;
     }
     let mut flag = false;
@@ -123,7 +271,7 @@ fn line_diff_char(set: Vec<String>) -> Option<Vec<usize>> {
             }
         }
         index += 1;
-        if index == set[0].len() {
+        if index == set[0].chars().count() {
             break;
         }
     }
@@ -133,63 +281,297 @@ fn line_diff_char(set: Vec<String>) -> Option<Vec<usize>> {
     Some(diff)
 }
 
-fn to_range(chars: Vec<char>) -> String {
-    let chars = chars.into_iter().sorted().collect::<Vec<_>>();
-    let mut range = String::new();
+// a merged line matching more paths than the lines it replaces
+// only the paths present in the tree were checked, so files created later may be ignored
+#[derive(Debug, Clone)]
+pub struct Generalization {
+    pub line: String,
+    pub merged: Vec<String>,   // lines replaced by the line
+    pub checked: Vec<PathBuf>, // paths in the tree found not to be matched
+    pub examples: Vec<String>, // hypothetical paths matched by the line only
+}
+
+fn generalize(
+    set: &[Pattern],
+    new_line: &Pattern,
+    checked: &[(PathBuf, bool)],
+) -> Option<Generalization> {
+    let examples = differences(set, new_line, 3);
+    if examples.is_empty() {
+        return None;
+    }
+    Some(Generalization {
+        line: new_line.to_string(),
+        merged: set.iter().map(|p| p.to_string()).collect(),
+        checked: checked.iter().map(|(path, _)| path.clone()).collect(),
+        examples,
+    })
+}
+
+// the children of the directories (paths with whether they are directories) not ignored by
+// the file, and the ones each line matches
+// the tree of lines does not change during the pass, so each of them is computed once
+struct Children {
+    root: PathBuf,
+    globals: Matcher,
+    not_ignored: HashMap<PathBuf, Vec<(PathBuf, bool)>>,
+    matched: HashMap<(PathBuf, String), Vec<usize>>,
+}
+impl Children {
+    fn new(root: &Path, tree: &DirectoryTree) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            globals: tree.globals_matcher(),
+            not_ignored: HashMap::new(),
+            matched: HashMap::new(),
+        }
+    }
+    fn get(&mut self, refactor: &Refactor, parent: &Path) -> &[(PathBuf, bool)] {
+        let (root, globals) = (&self.root, &self.globals);
+        self.not_ignored
+            .entry(parent.to_path_buf())
+            .or_insert_with(|| {
+                read_children(&root.join(parent.strip_prefix("/").unwrap_or(parent)))
+                    .into_iter()
+                    .map(|(name, is_dir)| (parent.join(name), is_dir))
+                    .filter(|(path, _)| !refactor.is_ignored(globals, path))
+                    .collect()
+            })
+    }
+    // indices of the children matched by the line
+    fn matched(&mut self, refactor: &Refactor, parent: &Path, line: &Pattern) -> &[usize] {
+        self.get(refactor, parent);
+        let children = &self.not_ignored[parent];
+        self.matched
+            .entry((parent.to_path_buf(), line.to_string()))
+            .or_insert_with(|| {
+                let matcher = Matcher::new(std::slice::from_ref(line));
+                children
+                    .iter()
+                    .positions(|(path, is_dir)| matcher.is_match(path, *is_dir))
+                    .collect()
+            })
+    }
+    // whether the line would ignore any of the children
+    fn matches_any(&mut self, refactor: &Refactor, parent: &Path, line: &Pattern) -> bool {
+        !self.matched(refactor, parent, line).is_empty()
+    }
+    // whether the line would ignore any of the children not matched by the lines of the set
+    fn matches_other(
+        &mut self,
+        refactor: &Refactor,
+        parent: &Path,
+        set: &[Pattern],
+        line: &Pattern,
+    ) -> bool {
+        let in_set = set
+            .iter()
+            .flat_map(|p| self.matched(refactor, parent, p).to_vec())
+            .collect::<BTreeSet<_>>();
+        self.matched(refactor, parent, line)
+            .iter()
+            .any(|i| !in_set.contains(i))
+    }
+}
+
+// class matching the characters (e.g. ['a', 'b', 'c', 'e'] -> [a-ce])
+fn to_class(chars: Vec<char>) -> Token {
+    let chars = chars.into_iter().sorted().dedup().collect::<Vec<_>>();
+    let mut items = Vec::new();
+    let mut push = |start: char, end: char| {
+        if start == end {
+            items.push(ClassItem::Char(start));
+        } else if end as u32 - start as u32 > 1 {
+            items.push(ClassItem::Range(start, end));
+        } else {
+            items.push(ClassItem::Char(start));
+            items.push(ClassItem::Char(end));
+        }
+    };
     let mut start = chars[0];
     let mut end = chars[0];
     for c in chars.iter().skip(1) {
-        if *c as u8 == end as u8 + 1 {
+        if *c as u32 == end as u32 + 1 {
             end = *c;
         } else {
-            if start == end {
-                range.push(start);
-            } else {
-                range.push(start);
-                if end as u8 - start as u8 > 1 {
-                    range.push('-');
-                }
-                range.push(end);
-            }
+            push(start, end);
             start = *c;
             end = *c;
         }
     }
-    if start == end {
-        range.push(start);
-    } else {
-        range.push(start);
-        if end as u8 - start as u8 > 1 {
-            range.push('-');
+    push(start, end);
+    Token::Class(items)
+}
+
+// replace the differing characters (character indices in the lines) with classes
+// each differing character should be a plain character in every line
+// return the positions ((segment, token) pairs) of the classes and the new line
+fn merge_chars(
+    patterns: &[Pattern],
+    diff_indices: &[usize],
+) -> Option<(Vec<(usize, usize)>, Pattern)> {
+    let positions = diff_indices
+        .iter()
+        .map(|index| {
+            patterns[0].locate(*index).filter(|(i, j)| {
+                patterns.iter().all(|p| {
+                    p.locate(*index) == Some((*i, *j))
+                        && matches!(p.segments[*i].get(*j), Some(Token::Char(_)))
+                })
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let mut segments = patterns[0].segments.clone();
+    for (i, j) in positions.iter() {
+        segments[*i][*j] = to_class(
+            patterns
+                .iter()
+                .flat_map(|p| p.segments[*i][*j].chars())
+                .flatten()
+                .collect(),
+        );
+    }
+    let merged = Pattern::from_parts(
+        patterns[0].negated,
+        patterns[0].anchored,
+        segments,
+        patterns[0].dir_only,
+    );
+    Some((positions, merged))
+}
+
+// ranges (character indices) to replace with wildcards in each line, for each way to merge them
+fn wildcard_ranges(set_str: &[String]) -> Vec<Vec<Vec<Option<Range<usize>>>>> {
+    let mut candidates = Vec::new();
+    if let Some((prefixes, suffixes)) = line_diff_string(set_str.to_vec()) {
+        // check all replace patterns: (prefix, suffix), (prefix, None), (None, suffix)
+        for (pre, suf) in [(true, true), (true, false), (false, true)] {
+            candidates.push(
+                prefixes
+                    .iter()
+                    .zip(suffixes.iter())
+                    .map(|(prefix, suffix)| {
+                        [(pre, prefix), (suf, suffix)]
+                            .into_iter()
+                            .filter(|(replace, _)| *replace)
+                            .map(|(_, range)| range.clone())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+    }
+    // or between the common affixes (e.g. tmp-1, tmp-2 -> tmp-*)
+    if let Some(middles) = line_diff_affix(set_str) {
+        candidates.push(middles.into_iter().map(|m| vec![Some(m)]).collect());
+    }
+    candidates
+}
+
+// single lines the set of lines can be merged into, the ones matching only the lines first
+fn single_line_candidates(set_str: &[String]) -> Vec<Pattern> {
+    let patterns = set_str
+        .iter()
+        .map(|line| Pattern::new_unchecked(line.clone()))
+        .collect::<Vec<_>>();
+    let mut candidates = Vec::new();
+    if let Some(diff_indices) = line_diff_char(set_str.to_vec()) {
+        if let Some((_, merged)) = merge_chars(&patterns, &diff_indices) {
+            candidates.push(merged);
+        }
+    }
+    for ranges in wildcard_ranges(set_str) {
+        let new_lines = set_str
+            .iter()
+            .zip(ranges.iter())
+            .map(|(line, affixes)| {
+                let affixes = affixes.iter().cloned().collect::<Option<Vec<_>>>()?;
+                replace_with_wildcard(line, &affixes)
+            })
+            .collect::<Option<Vec<_>>>();
+        if let Some(new_lines) = new_lines {
+            if new_lines.iter().map(|p| p.to_string()).all_equal() {
+                candidates.push(new_lines[0].clone());
+            }
         }
-        range.push(end);
     }
-    range
+    candidates
 }
 
-fn replace_ranges_with_wildcard(orig: &str, ranges: Vec<&(usize, String)>) -> String {
-    let mut new_line = orig.to_string();
-    for (index, range) in ranges.iter() {
-        let len = 2 + range.len();
-        let start = new_line.chars().take(*index).collect::<String>();
-        let wildcards = iter::repeat('*').take(len).collect::<String>();
-        let end = new_line.chars().skip(*index + len).collect::<String>();
-        new_line = format!("{}{}{}", start, wildcards, end);
-    }
-    Regex::new(r"\*+")
-        .unwrap()
-        .replace_all(&new_line, "*")
-        .to_string()
+// replace the characters in the ranges (character indices in the line) with wildcards
+fn replace_with_wildcard(line: &str, ranges: &[Range<usize>]) -> Option<Pattern> {
+    let pattern = Pattern::new_unchecked(line.to_string());
+    let mut segments = pattern.segments.clone();
+    // from right to left, so that the positions stay valid
+    for range in ranges.iter().sorted_by_key(|range| range.start).rev() {
+        let (i, start) = pattern.locate(range.start)?;
+        let (j, end) = pattern.locate(range.end)?;
+        if i != j {
+            return None;
+        }
+        segments[i].splice(start..end, [Token::Wildcard]);
+    }
+    Some(Pattern::from_parts(
+        pattern.negated,
+        pattern.anchored,
+        segments,
+        pattern.dir_only,
+    ))
 }
 
-fn merge_ranges(ranges: Vec<&str>) -> String {
-    let expanded = ranges.into_iter().map(|range| {
-        expand_range(range.to_string())
-            .into_iter()
-            .map(|x| x.chars().nth(0).unwrap())
-    });
-    let all_chars = expanded.clone().flatten().unique().collect::<Vec<_>>();
-    format!("[{}]", to_range(all_chars))
+// replace the classes at the positions ((segment, token) pairs) with wildcards
+fn replace_ranges_with_wildcard(orig: &Pattern, positions: Vec<&(usize, usize)>) -> Pattern {
+    let mut segments = orig.segments.clone();
+    for (i, j) in positions {
+        segments[*i][*j] = Token::Wildcard;
+    }
+    for segment in segments.iter_mut() {
+        segment.dedup_by(|a, b| *a == Token::Wildcard && *b == Token::Wildcard);
+    }
+    Pattern::from_parts(orig.negated, orig.anchored, segments, orig.dir_only)
+}
+
+// None if any of the classes is negated
+fn merge_ranges(ranges: Vec<&Token>) -> Option<Token> {
+    let chars = ranges
+        .into_iter()
+        .map(|range| range.chars())
+        .collect::<Option<Vec<_>>>()?;
+    Some(to_class(chars.into_iter().flatten().collect()))
+}
+
+// merge patterns differing only in their classes (e.g. a/[b-c], a/[df] -> a/[b-df])
+fn merge_classes(patterns: &[Pattern]) -> Option<Pattern> {
+    let first = &patterns[0];
+    if !patterns.iter().all(|p| {
+        p.negated == first.negated
+            && p.anchored == first.anchored
+            && p.dir_only == first.dir_only
+            && p.segments.len() == first.segments.len()
+            && p.segments
+                .iter()
+                .zip(first.segments.iter())
+                .all(|(a, b)| a.len() == b.len())
+    }) {
+        return None;
+    }
+    let mut segments = first.segments.clone();
+    for (i, segment) in segments.iter_mut().enumerate() {
+        for (j, token) in segment.iter_mut().enumerate() {
+            let tokens = patterns.iter().map(|p| &p.segments[i][j]);
+            if tokens.clone().all(|t| matches!(t, Token::Class(_))) {
+                *token = merge_ranges(tokens.collect())?;
+            } else if !tokens.clone().all_equal() {
+                return None;
+            }
+        }
+    }
+    Some(Pattern::from_parts(
+        first.negated,
+        first.anchored,
+        segments,
+        first.dir_only,
+    ))
 }
 
 #[allow(dead_code)]
@@ -211,6 +593,97 @@ fn binomial_coefficient(n: usize, k: usize) -> Option<usize> {
     Some(result)
 }
 
+// the paths in the tree with whether the file ignores them (directories ignored as a whole are
+// not walked into), walked once for each state of the file so that a candidate line is only
+// matched against them
+#[derive(Default)]
+struct Ignored {
+    lines: Option<Vec<String>>,
+    paths: Vec<(PathBuf, bool, bool)>,
+}
+impl Ignored {
+    fn update(&mut self, root: &Path, lines: &[String]) {
+        if self.lines.as_deref() == Some(lines) {
+            return;
+        }
+        let matcher = Matcher::new(
+            &lines
+                .iter()
+                .map(|line| Pattern::new_unchecked(line.clone()))
+                .collect::<Vec<_>>(),
+        );
+        self.paths = Walker::new(root, |dir| matcher.is_ignored(dir, true))
+            .map(|(path, is_dir)| {
+                let ignored = matcher.is_ignored(&path, is_dir);
+                (path, is_dir, ignored)
+            })
+            .collect();
+        self.lines = Some(lines.to_vec());
+    }
+    // whether the new lines ignore the same paths
+    // the directories not walked into are ignored by both, as their statuses are the same
+    fn same(&self, new: &Matcher) -> bool {
+        self.paths
+            .iter()
+            .all(|(path, is_dir, ignored)| new.is_ignored(path, *is_dir) == *ignored)
+    }
+    // the paths walked that none of the lines of the set matches
+    fn checked(&self, set: &[Pattern]) -> Vec<(PathBuf, bool)> {
+        let matcher = Matcher::new(set);
+        self.paths
+            .iter()
+            .filter(|(path, is_dir, _)| !matcher.is_match(path, *is_dir))
+            .map(|(path, is_dir, _)| (path.clone(), *is_dir))
+            .collect()
+    }
+}
+
+// the lines of the other polarity between the first and the last lines of the set
+// the merged line takes the place of the first one, so it should overlap none of them
+fn lines_between(file: &File, set: &[String]) -> Vec<Pattern> {
+    let positions = set
+        .iter()
+        .filter_map(|line| {
+            file.content
+                .iter()
+                .position(|l| matches!(&l.content, Content::Pattern(p) if p == line))
+        })
+        .collect::<Vec<_>>();
+    let (Some(first), Some(last)) = (positions.iter().min(), positions.iter().max()) else {
+        return Vec::new();
+    };
+    let negated = file.content[*first].content.unwrap().starts_with('!');
+    file.content[*first..*last]
+        .iter()
+        .filter_map(|line| match &line.content {
+            Content::Pattern(p) if p.starts_with('!') != negated => {
+                Some(Pattern::new_unchecked(p.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// lines the set of lines with the same name in different directories can be promoted to
+// (e.g. a/.DS_Store, b/.DS_Store -> .DS_Store, or */.DS_Store if they are at the same depth)
+fn promotions(set: &[Pattern]) -> Vec<Pattern> {
+    let first = &set[0];
+    let last = first.last_segment().to_vec();
+    let mut candidates = vec![Pattern::from_parts(
+        false,
+        false,
+        vec![last.clone()],
+        first.dir_only,
+    )];
+    let depth = first.segments.len();
+    if depth > 1 && set.iter().all(|p| p.segments.len() == depth) {
+        let mut segments = vec![vec![Token::Wildcard]; depth - 1];
+        segments.push(last);
+        candidates.push(Pattern::from_parts(false, false, segments, first.dir_only));
+    }
+    candidates
+}
+
 impl Refactor {
     pub fn merge(&mut self) -> &mut Self {
         // iterate over all of the sets of lines, from largest to smallest
@@ -220,24 +693,51 @@ impl Refactor {
         }
 
         let line_num = file.content.len();
+        let strict = self.strict();
+        let max_combinations = self.state.max_combinations;
+        let mut children = Children::new(&root, &tree);
+        let mut ignored = Ignored::default();
+        // a global line matches paths created later in any directory
+        if !strict {
+            self.merge_across_directories(&mut ignored, verbose);
+        }
+        // numbers of different lengths cannot be merged character-wise
+        self.merge_numbers(&mut children, &mut ignored, verbose);
         'outer: loop {
             let file = self.file().clone();
+            // lines of the tree (e.g. not the global or the negated ones)
+            let lines = file
+                .content
+                .iter()
+                .filter_map(|line| match &line.content {
+                    // protected lines are not merged
+                    Content::Pattern(p) if !p.starts_with('!') && !file.is_protected(line) => {
+                        Some(p.clone())
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let matcher = Matcher::new(
+                &lines
+                    .iter()
+                    .map(|line| Pattern::new_unchecked(line.clone()).anchor())
+                    .collect::<Vec<_>>(),
+            );
+            let matched = tree
+                .node_line_map
+                .keys()
+                .flat_map(|key| matcher.matches(key, true))
+                .collect::<BTreeSet<_>>();
             // if verbose {
             //     printv!(file.content);
             // }
             for size in (2..=file.content.len()).rev() {
                 // println!("size: {}", size);
-                let filtered_lines = file
-                    .content
+                let filtered_lines = lines
                     .iter()
-                    .filter(|line| matches!(line.content, Content::Pattern(_)))
-                    .map(|line| PathBuf::from(line.content.unwrap()))
-                    // .filter(|line_str| self.is_normally_ignored(Path::new(line_str)))
-                    .filter(|line_str| {
-                        tree.node_line_map
-                            .keys()
-                            .any(|pat| does_match(pat, &line_str.to_str().unwrap().to_string()))
-                    });
+                    .enumerate()
+                    .filter(|(i, _)| matched.contains(i))
+                    .map(|(_, line)| PathBuf::from(line));
                 // let sets_size = binomial_coefficient(filtered_lines.clone().count(), size);
                 // match sets_size {
                 //     Some(s) => {
@@ -252,7 +752,7 @@ impl Refactor {
                 } else {
                     continue;
                 };
-                if sets.clone().nth(10000).is_some() {
+                if sets.clone().nth(max_combinations).is_some() {
                     continue;
                 }
                 for set in sets {
@@ -267,20 +767,21 @@ impl Refactor {
                         continue;
                     }
 
-                    let parent_tree =
-                        FsTree::read_at(root.join(parent.strip_prefix("/").unwrap_or(parent)))
-                            .expect(&format!(
-                                "Failed to read tree at: {:?}",
-                                root.join(parent.strip_prefix("/").unwrap_or(parent))
-                            ));
-                    let not_ignored_children = parent_tree
-                        .children()
-                        .unwrap()
-                        .keys()
-                        .map(|path| parent.join(path.strip_prefix("/").unwrap_or(path)))
-                        .filter(|path| !self.is_ignored(path))
+                    let set_str = set.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+                    // directory-only lines should not be merged with the others
+                    if !set_str.iter().map(|line| line.ends_with('/')).all_equal() {
+                        continue;
+                    }
+                    // in strict mode, a new line should match no path other than the ones of the set
+                    let set_patterns = set_str
+                        .iter()
+                        .map(|line| Pattern::new_unchecked(line.clone()))
                         .collect::<Vec<_>>();
-                    let mut set_str = set.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+                    let lines_between = lines_between(&file, &set_str);
+                    let equivalent = |new_line: &Pattern| {
+                        (!strict || subsumes_all(&set_patterns, new_line))
+                            && !lines_between.iter().any(|line| overlaps(line, new_line))
+                    };
 
                     let can_range;
                     let diff_indices;
@@ -304,140 +805,87 @@ impl Refactor {
                         can_range = false;
                     }
 
-                    if let Some(ranges) = line_diff_string(set_str.clone()) {
+                    for ranges in wildcard_ranges(&set_str) {
                         // can be merged with wildcard
-                        // check all replace patterns: (prefix, suffix), (prefix, None), (None, suffix)
-                        for (pre, suf) in [(true, true), (true, false), (false, true)] {
-                            let orig = set_str[0].clone();
-                            let mut new_line = orig.clone();
-                            let mut offset = 0;
-                            if pre {
-                                if let Some(pre_range) = &ranges.0[0] {
-                                    new_line = format!(
-                                        "{}*{}",
-                                        new_line.chars().take(pre_range.start).collect::<String>(),
-                                        new_line.chars().skip(pre_range.end).collect::<String>()
-                                    );
-                                    offset += pre_range.end - pre_range.start - 1;
-                                } else {
-                                    continue;
-                                }
+                        let Some(new_line) = ranges[0]
+                            .iter()
+                            .cloned()
+                            .collect::<Option<Vec<_>>>()
+                            .and_then(|affixes| replace_with_wildcard(&set_str[0], &affixes))
+                        else {
+                            continue;
+                        };
+                        let new_lines = set_str
+                            .iter()
+                            .zip(ranges.iter())
+                            .map(|(line, affixes)| {
+                                let affixes = affixes.iter().flatten().cloned().collect_vec();
+                                replace_with_wildcard(line, &affixes)
+                            })
+                            .collect::<Vec<_>>();
+                        if !children.matches_any(self, parent, &new_line)
+                            && new_lines.iter().flatten().all(equivalent)
+                        {
+                            if verbose == 1 {
+                                println!("Merging with wildcard:\r\n");
+                                // printv!(new_line);
                             }
-                            if suf {
-                                if let Some(suf_range) = &ranges.1[0] {
-                                    new_line = format!(
-                                        "{}*{}",
-                                        new_line
-                                            .chars()
-                                            .take(suf_range.start - offset)
-                                            .collect::<String>(),
-                                        new_line
-                                            .chars()
-                                            .skip(suf_range.end - offset)
-                                            .collect::<String>()
+                            let file = self.file_mut();
+                            for (line, new_line) in set_str.iter().zip(new_lines.iter()) {
+                                if let Some(new_line) = new_line {
+                                    file.replace_line(
+                                        line.to_string(),
+                                        new_line.to_string(),
+                                        verbose,
                                     );
-                                } else {
-                                    continue;
                                 }
                             }
-                            if not_ignored_children
+                            file.remove_dupl();
+                            let not_ignored_children = children.get(self, parent).to_vec();
+                            let generalizations = new_lines
                                 .iter()
-                                .all(|child| !does_match(child, &new_line))
-                            {
-                                if verbose == 1 {
-                                    println!("Merging with wildcard:\r\n");
-                                    // printv!(new_line);
-                                }
-                                let file = self.file_mut();
-                                for (i, line) in set_str.iter().enumerate() {
-                                    let mut new_line = line.clone();
-                                    if pre {
-                                        if let Some(pre_range) = &ranges.0[i] {
-                                            new_line = format!(
-                                                "{}*{}",
-                                                new_line
-                                                    .chars()
-                                                    .take(pre_range.start)
-                                                    .collect::<String>(),
-                                                new_line
-                                                    .chars()
-                                                    .skip(pre_range.end)
-                                                    .collect::<String>()
-                                            );
-                                            offset += pre_range.end - pre_range.start - 1;
-                                        }
-                                    }
-                                    if suf {
-                                        if let Some(suf_range) = &ranges.1[i] {
-                                            new_line = format!(
-                                                "{}*{}",
-                                                new_line
-                                                    .chars()
-                                                    .take(suf_range.start - offset)
-                                                    .collect::<String>(),
-                                                new_line
-                                                    .chars()
-                                                    .skip(suf_range.end - offset)
-                                                    .collect::<String>()
-                                            );
-                                        }
-                                    }
-                                    file.replace_line(line.to_string(), new_line.clone(), verbose);
-                                }
-                                file.remove_dupl();
-                                // self.halt();
-                                continue 'outer;
-                            }
+                                .flatten()
+                                .unique_by(|new_line| new_line.to_string())
+                                .filter_map(|new_line| {
+                                    generalize(&set_patterns, new_line, &not_ignored_children)
+                                })
+                                .collect::<Vec<_>>();
+                            self.state.generalizations.extend(generalizations);
+                            // self.halt();
+                            continue 'outer;
                         }
                     }
 
                     if can_range {
-                        let file = self.file_mut();
                         if let Some(diff_indices) = diff_indices {
+                            let patterns = set_str
+                                .iter()
+                                .map(|line| Pattern::new_unchecked(line.clone()))
+                                .collect::<Vec<_>>();
+                            let Some((positions, orig)) = merge_chars(&patterns, &diff_indices)
+                            else {
+                                continue;
+                            };
                             if verbose == 1 {
                                 println!("Merging with range:\r\n");
                                 printv!(diff_indices);
                             }
-                            let mut offset = 0;
-                            let mut ranges = Vec::new();
-                            for index in diff_indices.iter() {
-                                // replace each line in the set with a new line with range notation at the index
-                                let diff_chars = set_str
-                                    .iter()
-                                    .map(|line_str| line_str.chars().nth(*index + offset).unwrap())
-                                    .collect::<Vec<char>>();
-                                let range_str = to_range(diff_chars.clone());
-                                for i in 0..set_str.len() {
-                                    let new_line = format!(
-                                        "{}[{}]{}",
-                                        set_str[i]
-                                            .chars()
-                                            .take(*index + offset)
-                                            .collect::<String>(),
-                                        range_str.clone(),
-                                        set_str[i]
-                                            .chars()
-                                            .skip(*index + 1 + offset)
-                                            .collect::<String>()
-                                    );
-                                    file.replace_line(
-                                        set_str[i].clone(),
-                                        new_line.clone(),
-                                        verbose,
-                                    );
-                                    set_str[i] = new_line;
-                                }
-                                ranges.push((*index + offset, range_str));
-                                offset += 2 + to_range(diff_chars.clone()).len() - 1;
+                            // the classes match every combination of the characters (e.g. a1x, b2x -> [ab][12]x
+                            // matches a2x too), which should not be existing paths not ignored
+                            if !equivalent(&orig)
+                                || children.matches_other(self, parent, &patterns, &orig)
+                            {
+                                continue;
+                            }
+                            let file = self.file_mut();
+                            for line in set_str.iter() {
+                                file.replace_line(line.clone(), orig.to_string(), verbose);
                             }
                             file.remove_dupl();
+                            let mut merged = orig.clone();
 
                             // check if any of the range notations can be replaced with a wildcard
-                            let orig = set_str[0].clone();
-                            'wildcard: for size_ranges in (1..=ranges.len()).rev() {
-                                if ranges.len() < size_ranges {
-                                    continue;
-                                }
+                            'wildcard: for size_ranges in (1..=positions.len()).rev() {
                                 // let sets_size = binomial_coefficient(ranges.len(), size_ranges);
                                 // match sets_size {
                                 //     Some(s) => {
@@ -447,22 +895,32 @@ impl Refactor {
                                 //     }
                                 //     None => continue,
                                 // }
-                                let sets_ranges = ranges.iter().combinations(size_ranges);
-                                if sets_ranges.clone().nth(10000).is_some() {
+                                let sets_ranges = positions.iter().combinations(size_ranges);
+                                if sets_ranges.clone().nth(max_combinations).is_some() {
                                     continue;
                                 }
                                 for set_ranges in sets_ranges {
                                     let new_line = replace_ranges_with_wildcard(&orig, set_ranges);
-                                    if not_ignored_children
-                                        .iter()
-                                        .all(|child| !does_match(child, &new_line))
+                                    if !children.matches_any(self, parent, &new_line)
+                                        && equivalent(&new_line)
                                     {
-                                        file.replace_line(orig, new_line.clone(), verbose);
+                                        self.file_mut().replace_line(
+                                            orig.to_string(),
+                                            new_line.to_string(),
+                                            verbose,
+                                        );
+                                        merged = new_line;
                                         break 'wildcard;
                                     }
                                 }
                             }
-                            file.remove_dupl();
+                            self.file_mut().remove_dupl();
+                            let not_ignored_children = children.get(self, parent).to_vec();
+                            if let Some(generalization) =
+                                generalize(&patterns, &merged, &not_ignored_children)
+                            {
+                                self.state.generalizations.push(generalization);
+                            }
                         }
                         continue 'outer;
                     }
@@ -470,6 +928,8 @@ impl Refactor {
             }
             break;
         }
+        // negated lines (e.g. the ones added by re_include) are merged separately
+        self.merge_negations(&mut ignored, verbose);
 
         // merge ranges (e.g. a/[b-c], a/[df] -> a/[b-df])
         let file = self.file();
@@ -477,9 +937,15 @@ impl Refactor {
             .content
             .iter()
             .filter_map(|line| match &line.content {
-                Content::Pattern(path) => {
-                    if path.contains('[') {
-                        Some(path)
+                Content::Pattern(path) if !file.is_protected(line) => {
+                    let pattern = Pattern::new_unchecked(path.clone());
+                    if pattern
+                        .segments
+                        .iter()
+                        .flatten()
+                        .any(|t| matches!(t, Token::Class(_)))
+                    {
+                        Some((path, pattern))
                     } else {
                         None
                     }
@@ -487,56 +953,40 @@ impl Refactor {
                 _ => None,
             })
             .collect::<Vec<_>>();
-        let separator = Regex::new(r"(\[.*?\])").unwrap();
         for size in (2..=sets.len()).rev() {
-            let sets = sets.iter().combinations(size);
-            'outer: for set in sets {
-                let parts = set
-                    .iter()
-                    .map(|x| separator.split(x).collect::<Vec<_>>())
-                    .collect::<Vec<_>>();
-                if parts.iter().all_equal() {
-                    let mut set_iter = set.iter();
-                    let mut ranges_table = separator
-                        .captures_iter(set_iter.next().unwrap())
-                        .map(|caps| vec![caps.get(1).unwrap().as_str()])
-                        .collect::<Vec<_>>();
-                    while let Some(pat) = set_iter.next() {
-                        let ranges = separator
-                            .captures_iter(pat)
-                            .map(|caps| caps.get(1).unwrap().as_str())
-                            .collect::<Vec<_>>();
-                        if ranges_table.len() != ranges.len() {
-                            continue 'outer;
-                        }
-                        for (i, range) in ranges.iter().enumerate() {
-                            ranges_table[i].push(range);
-                        }
-                    }
-                    let merged_ranges = ranges_table
-                        .into_iter()
-                        .map(|ranges| merge_ranges(ranges))
-                        .collect::<Vec<_>>();
-                    let new_line = parts.iter().nth(0).unwrap().into_iter().enumerate().fold(
-                        String::new(),
-                        |acc, (i, part)| {
-                            format!(
-                                "{}{}{}",
-                                acc,
-                                part,
-                                if i < merged_ranges.len() {
-                                    merged_ranges[i].clone()
-                                } else {
-                                    "".to_string()
-                                }
-                            )
-                        },
-                    );
+            for set in sets.iter().combinations(size) {
+                // skip lines already merged
+                let current = self.file();
+                if !set.iter().all(|(line, _)| {
+                    current
+                        .content
+                        .iter()
+                        .any(|l| l.content == Content::Pattern(line.to_string()))
+                }) {
+                    continue;
+                }
+                let patterns = set.iter().map(|(_, p)| p.clone()).collect::<Vec<_>>();
+                let set_str = set.iter().map(|(line, _)| line.to_string()).collect_vec();
+                let lines_between = lines_between(&current, &set_str);
+                // outside of strict mode, the merged line is checked against the tree
+                if let Some(new_line) = merge_classes(&patterns).filter(|new_line| {
+                    (if strict {
+                        subsumes_all(&patterns, new_line)
+                    } else {
+                        self.replaces_same(&mut ignored, &set_str, new_line)
+                    }) && !lines_between.iter().any(|line| overlaps(line, new_line))
+                }) {
                     let file = self.file_mut();
-                    for line in set {
-                        file.replace_line(line.to_string(), new_line.clone(), verbose);
+                    for (line, _) in set {
+                        file.replace_line(line.to_string(), new_line.to_string(), verbose);
                     }
                     file.remove_dupl();
+                    // classes at several positions combine into paths none of the lines matched
+                    if let Some(generalization) =
+                        generalize(&patterns, &new_line, &ignored.checked(&patterns))
+                    {
+                        self.state.generalizations.push(generalization);
+                    }
                 }
             }
         }
@@ -544,6 +994,265 @@ impl Refactor {
         self.finish(true, "merge", line_num);
         self
     }
+
+    // merge lines ignoring the same name in different directories into a single line, if it
+    // ignores the same paths in the tree
+    fn merge_across_directories(&mut self, ignored: &mut Ignored, verbose: u8) {
+        let file = self.file();
+        let lines = file
+            .content
+            .iter()
+            .filter_map(|line| match &line.content {
+                Content::Pattern(p) if !file.is_protected(line) => {
+                    Some((p.clone(), Pattern::new_unchecked(p.clone())))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let sets = lines
+            .iter()
+            .filter(|(_, p)| {
+                // lines anchored to the root (e.g. `/b`) are not meant to apply elsewhere
+                !p.negated
+                    && p.segments.len() > 1
+                    && p.last_segment() != [Token::Wildcard, Token::Wildcard]
+            })
+            .into_group_map_by(|(_, p)| (p.last_segment().to_vec(), p.dir_only))
+            .into_values()
+            .filter(|set| set.len() > 1)
+            .sorted_by_key(|set| set[0].0.clone())
+            .collect::<Vec<_>>();
+        for set in sets {
+            let (set_str, set_patterns): (Vec<_>, Vec<_>) = set.into_iter().cloned().unzip();
+            for new_line in promotions(&set_patterns) {
+                if !self.replaces_same(ignored, &set_str, &new_line) {
+                    continue;
+                }
+                if verbose == 1 {
+                    println!("Merging across directories:\r\n");
+                }
+                let file = self.file_mut();
+                for line in set_str.iter() {
+                    file.replace_line(line.clone(), new_line.to_string(), verbose);
+                }
+                file.remove_dupl();
+                if let Some(generalization) =
+                    generalize(&set_patterns, &new_line, &ignored.checked(&set_patterns))
+                {
+                    self.state.generalizations.push(generalization);
+                }
+                break;
+            }
+        }
+    }
+
+    // whether the file ignores the same paths in the tree with the lines of the set replaced
+    // by the new line (at the place of the first one)
+    fn replaces_same(&self, ignored: &mut Ignored, set_str: &[String], new_line: &Pattern) -> bool {
+        let lines = self
+            .file()
+            .content
+            .iter()
+            .filter_map(|line| match &line.content {
+                Content::Pattern(p) => Some(p.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        ignored.update(self.root(), &lines);
+        let new = lines
+            .iter()
+            .map(|line| {
+                if set_str.contains(line) {
+                    new_line.clone()
+                } else {
+                    Pattern::new_unchecked(line.clone())
+                }
+            })
+            .unique_by(|p| p.to_string())
+            .collect::<Vec<_>>();
+        ignored.same(&Matcher::new(&new))
+    }
+
+    fn merge_numbers(&mut self, children: &mut Children, ignored: &mut Ignored, verbose: u8) {
+        let file = self.file();
+        let sets = file
+            .content
+            .iter()
+            .filter_map(|line| match &line.content {
+                Content::Pattern(p) if !file.is_protected(line) => Some(p.clone()),
+                _ => None,
+            })
+            .filter(|line| {
+                // only literal lines, the numbers being replaced with classes
+                let pattern = Pattern::new_unchecked(line.clone());
+                !pattern.negated
+                    && pattern
+                        .segments
+                        .iter()
+                        .flatten()
+                        .all(|t| matches!(t, Token::Char(_)))
+            })
+            .filter_map(|line| {
+                let (before, number, after) = split_number(&line)?;
+                Some(((before, after), (line, number)))
+            })
+            .into_group_map()
+            .into_iter()
+            .filter(|(_, set)| set.len() > 1)
+            .sorted()
+            .collect::<Vec<_>>();
+        for ((before, after), set) in sets {
+            let (set_str, numbers): (Vec<_>, BTreeSet<_>) = set.into_iter().unzip();
+            let set_patterns = set_str
+                .iter()
+                .map(|line| Pattern::new_unchecked(line.clone()))
+                .collect::<Vec<_>>();
+            let parent = PathBuf::from(before.trim_start_matches('/'));
+            let parent = if before.ends_with('/') {
+                parent
+            } else {
+                parent.parent().map(Path::to_path_buf).unwrap_or_default()
+            };
+            // a wildcard in place of the numbers, or the classes matching exactly the numbers
+            let wildcard = Pattern::new_unchecked(format!("{}*{}", before, after));
+            // a line without a slash matches at any depth, not only in the directory
+            let anywhere = !wildcard.anchored && wildcard.segments.len() == 1;
+            let new_lines = if !self.strict()
+                && wildcard.last_segment().len() > 1
+                && !children.matches_any(self, &parent, &wildcard)
+                && (!anywhere || self.replaces_same(ignored, &set_str, &wildcard))
+            {
+                vec![wildcard]
+            } else {
+                number_lines(&before, &numbers, &after)
+            };
+            let lines_between = lines_between(&self.file(), &set_str);
+            if new_lines.len() >= set_str.len()
+                || new_lines
+                    .iter()
+                    .any(|new_line| lines_between.iter().any(|line| overlaps(line, new_line)))
+            {
+                continue;
+            }
+            if verbose == 1 {
+                println!("Merging numbers:\r\n");
+            }
+            // the new lines take the places of the first lines of the set
+            let file = self.file_mut();
+            for (i, line) in set_str.iter().enumerate() {
+                match new_lines.get(i) {
+                    Some(new_line) => {
+                        file.replace_line(line.clone(), new_line.to_string(), verbose)
+                    }
+                    None => file.remove_line(line.clone(), verbose),
+                }
+            }
+            file.remove_dupl();
+            let not_ignored_children = children.get(self, &parent).to_vec();
+            for new_line in new_lines.iter() {
+                if let Some(generalization) =
+                    generalize(&set_patterns, new_line, &not_ignored_children)
+                {
+                    self.state.generalizations.push(generalization);
+                }
+            }
+        }
+    }
+
+    // a merged negation should re-include no path that should stay ignored
+    fn merge_negations(&mut self, ignored: &mut Ignored, verbose: u8) {
+        let strict = self.strict();
+        let max_combinations = self.state.max_combinations;
+        'outer: loop {
+            let file = self.file();
+            let lines = file
+                .content
+                .iter()
+                .filter_map(|line| match &line.content {
+                    Content::Pattern(p) if !file.is_protected(line) => {
+                        Some((p.clone(), Pattern::new_unchecked(p.clone())))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            // negated lines in the same directory
+            let groups = lines
+                .iter()
+                .filter(|(_, p)| p.negated)
+                .into_group_map_by(|(line, p)| {
+                    let line = line.trim_end_matches('/');
+                    let parent = line.rsplit_once('/').map_or("", |(parent, _)| parent);
+                    (parent.to_string(), p.dir_only)
+                })
+                .into_values()
+                .filter(|group| group.len() > 1)
+                .sorted_by_key(|group| group[0].0.clone())
+                .collect::<Vec<_>>();
+            for group in groups {
+                for size in (2..=group.len()).rev() {
+                    let sets = group.iter().combinations(size);
+                    if sets.clone().nth(max_combinations).is_some() {
+                        continue;
+                    }
+                    for set in sets {
+                        let (set_str, set_patterns): (Vec<_>, Vec<_>) =
+                            set.into_iter().map(|line| (*line).clone()).unzip();
+                        // merged as ignore lines, then negated again
+                        let positives = set_str
+                            .iter()
+                            .map(|line| line[1..].to_string())
+                            .collect::<Vec<_>>();
+                        for positive in single_line_candidates(&positives) {
+                            // negated wildcards (e.g. `!a/keep*`) are not valid lines (see
+                            // `parse`), so only classes are kept (e.g. `!a/keep[12]`)
+                            if positive
+                                .segments
+                                .iter()
+                                .flatten()
+                                .any(|t| matches!(t, Token::Wildcard | Token::Any))
+                            {
+                                continue;
+                            }
+                            let new_line = Pattern::from_parts(
+                                true,
+                                positive.anchored,
+                                positive.segments,
+                                positive.dir_only,
+                            );
+                            if strict
+                                && (!subsumes_all(&set_patterns, &new_line)
+                                    || lines_between(&file, &set_str)
+                                        .iter()
+                                        .any(|line| overlaps(line, &new_line)))
+                            {
+                                continue;
+                            }
+                            if !self.replaces_same(ignored, &set_str, &new_line) {
+                                continue;
+                            }
+                            if verbose == 1 {
+                                println!("Merging negations:\r\n");
+                            }
+                            let file = self.file_mut();
+                            for line in set_str.iter() {
+                                file.replace_line(line.clone(), new_line.to_string(), verbose);
+                            }
+                            file.remove_dupl();
+                            if let Some(generalization) = generalize(
+                                &set_patterns,
+                                &new_line,
+                                &ignored.checked(&set_patterns),
+                            ) {
+                                self.state.generalizations.push(generalization);
+                            }
+                            continue 'outer;
+                        }
+                    }
+                }
+            }
+            break;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -566,6 +1275,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_rerun() {
+        // merged negations are valid lines, so the output can be refactored again
+        let path = PathBuf::from("tests/data/merge/input/16/gitignore");
+        let result = Refactor::run(&path, 1);
+        let text = result.file().to_text();
+        assert!(text.contains("!b/[ab][ab].txt"));
+        assert!(crate::parse::validate(&text)
+            .iter()
+            .all(|d| d.severity != crate::parse::Severity::Error));
+        let root = result.root().clone();
+        let rerun = Refactor::from_file(result.file(), root, 1, 0)
+            .preprocess()
+            .containment()
+            .dead()
+            .negation()
+            .re_include()
+            .merge()
+            .postprocess()
+            .file()
+            .to_text();
+        assert_eq!(rerun, text);
+    }
+
+    #[test]
+    fn test_generalizations() {
+        let path = PathBuf::from("tests/data/merge/input/5/gitignore");
+        let refactor = &mut Refactor::new(&path, 1, 0);
+        let result = refactor.preprocess().merge().postprocess();
+        let generalizations = result.generalizations();
+        assert_eq!(generalizations.len(), 1);
+        let g = &generalizations[0];
+        assert_eq!(g.line, "a/x*/");
+        assert_eq!(g.merged, vec!["a/x1/", "a/x2/"]);
+        assert!(g.checked.contains(&PathBuf::from("a/x3")));
+        assert!(!g.checked.contains(&PathBuf::from("a/x4")));
+        assert!(!g.examples.is_empty());
+        // equivalent merges are not reported
+        let path = PathBuf::from("tests/data/merge/input/1/gitignore");
+        let refactor = &mut Refactor::new(&path, 1, 0);
+        assert!(refactor
+            .preprocess()
+            .merge()
+            .postprocess()
+            .generalizations()
+            .is_empty());
+        // merged classes are checked against the tree too
+        let path = PathBuf::from("tests/data/merge/input/14/gitignore");
+        let refactor = &mut Refactor::new(&path, 1, 0);
+        let result = refactor.preprocess().merge().postprocess();
+        let g = result.generalizations().last().unwrap();
+        assert_eq!(g.line, "a/b[1-357]/test.py[cdo]");
+        assert!(g.checked.contains(&PathBuf::from("a/b5/test.py")));
+        assert!(!g.checked.contains(&PathBuf::from("a/b5/test.pyo")));
+    }
+
     #[test]
     fn test_line_diff_string() {
         assert_eq!(
@@ -611,6 +1376,66 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_line_diff_affix() {
+        let diff = |lines: Vec<&str>| {
+            line_diff_affix(&lines.iter().map(|l| l.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            diff(vec!["out/test_output_a", "out/test_output_b"]),
+            Some(vec![16..17, 16..17])
+        );
+        assert_eq!(diff(vec!["tmp-1234", "tmp-5678/"]), Some(vec![4..8, 4..8]));
+        assert_eq!(
+            diff(vec!["build-x86.log", "build-arm.log", "build-riscv.log"]),
+            Some(vec![6..9, 6..9, 6..11])
+        );
+        assert_eq!(diff(vec!["x1.cache", "y22.cache"]), Some(vec![0..2, 0..3]));
+        // too short or not delimited
+        assert_eq!(diff(vec!["a_1", "a_2"]), None);
+        assert_eq!(diff(vec!["foo1", "foo2"]), None);
+        assert_eq!(diff(vec!["rust", "python"]), None);
+    }
+
+    #[test]
+    fn test_split_number() {
+        let parts = |a: &str, b: &str, c: &str| Some((a.to_string(), b.to_string(), c.to_string()));
+        assert_eq!(split_number("log12"), parts("log", "12", ""));
+        assert_eq!(split_number("a1/log2.txt"), parts("a1/log", "2", ".txt"));
+        assert_eq!(split_number("/v1.2/"), parts("/v1.", "2", "/"));
+        assert_eq!(split_number("a/12"), parts("a/", "12", ""));
+        assert_eq!(split_number("a1/log"), None);
+    }
+
+    #[test]
+    fn test_number_lines() {
+        let lines = |numbers: Vec<&str>| {
+            number_lines(
+                "log",
+                &numbers.iter().map(|n| n.to_string()).collect(),
+                ".txt",
+            )
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+        };
+        let numbers = (1..=25).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            lines(numbers.iter().map(|n| n.as_str()).collect()),
+            vec!["log[1-9].txt", "log1[0-9].txt", "log2[0-5].txt"]
+        );
+        assert_eq!(
+            lines(vec!["10", "11", "20", "21", "35"]),
+            vec!["log[12][01].txt", "log35.txt"]
+        );
+        assert_eq!(
+            lines(vec!["01", "02", "3"]),
+            vec!["log3.txt", "log0[12].txt"]
+        );
+        // classes for each digit would match 109 and 190 too
+        assert_eq!(lines(vec!["100", "199"]), vec!["log100.txt", "log199.txt"]);
+    }
+
     #[test]
     fn test_line_diff_char() {
         assert_eq!(
@@ -632,49 +1457,94 @@ mod tests {
     }
 
     #[test]
-    fn test_to_range() {
-        assert_eq!(to_range(vec!['a', 'b', 'c', 'd']), "a-d".to_string());
-        assert_eq!(to_range(vec!['c', 'b', 'a', 'e']), "a-ce".to_string());
-        assert_eq!(to_range(vec!['a', 'b', 'd', 'e']), "abde".to_string());
-        assert_eq!(to_range(vec!['a', 'c', 'd', 'e']), "ac-e".to_string());
-        assert_eq!(to_range(vec!['a', 'b', 'c']), "a-c".to_string());
-        assert_eq!(to_range(vec!['a', 'b']), "ab".to_string());
-        assert_eq!(to_range(vec!['a']), "a".to_string());
+    fn test_to_class() {
+        let to_string = |chars: Vec<char>| to_class(chars).to_string();
+        assert_eq!(to_string(vec!['a', 'b', 'c', 'd']), "[a-d]".to_string());
+        assert_eq!(to_string(vec!['c', 'b', 'a', 'e']), "[a-ce]".to_string());
+        assert_eq!(to_string(vec!['a', 'b', 'd', 'e']), "[abde]".to_string());
+        assert_eq!(to_string(vec!['a', 'c', 'd', 'e']), "[ac-e]".to_string());
+        assert_eq!(to_string(vec!['a', 'b', 'c']), "[a-c]".to_string());
+        assert_eq!(to_string(vec!['a', 'b']), "[ab]".to_string());
+        assert_eq!(to_string(vec!['a']), "[a]".to_string());
+        assert_eq!(to_string(vec!['â', 'à', 'á', 'é']), "[à-âé]".to_string());
+        assert_eq!(to_string(vec!['a', 'b', 'ţ']), "[abţ]".to_string());
     }
 
     #[test]
     fn test_replace_ranges_with_wildcard() {
+        let replace = |line: &str, positions: Vec<&(usize, usize)>| {
+            replace_ranges_with_wildcard(&Pattern::new_unchecked(line.to_string()), positions)
+                .to_string()
+        };
         assert_eq!(
-            replace_ranges_with_wildcard("a[1-3]b[4-6]c", vec![&(1, "1-3".to_string())]),
+            replace("a[1-3]b[4-6]c", vec![&(0, 1)]),
             "a*b[4-6]c".to_string()
         );
         assert_eq!(
-            replace_ranges_with_wildcard(
-                "a[1-3]b[4-6]c",
-                vec![&(1, "1-3".to_string()), &(7, "4-6".to_string())]
-            ),
+            replace("a[1-3]b[4-6]c", vec![&(0, 1), &(0, 3)]),
             "a*b*c".to_string()
         );
         assert_eq!(
-            replace_ranges_with_wildcard("a[1-3][4-6]c", vec![&(6, "4-6".to_string())]),
+            replace("a[1-3][4-6]c", vec![&(0, 2)]),
             "a[1-3]*c".to_string()
         );
         assert_eq!(
-            replace_ranges_with_wildcard(
-                "a[1-3][4-6]c",
-                vec![&(1, "1-3".to_string()), &(6, "4-6".to_string())]
-            ),
+            replace("a[1-3][4-6]c", vec![&(0, 1), &(0, 2)]),
             "a*c".to_string()
         );
     }
 
+    #[test]
+    fn test_replace_with_wildcard() {
+        assert_eq!(
+            replace_with_wildcard("a/a123.txt", &[Range { start: 2, end: 6 }])
+                .map(|p| p.to_string()),
+            Some("a/*.txt".to_string())
+        );
+        assert_eq!(
+            replace_with_wildcard("a/[0-9]1.pyc", &[2..8, 11..12]).map(|p| p.to_string()),
+            Some("a/*.py*".to_string())
+        );
+        // ranges should not span a slash
+        assert_eq!(
+            replace_with_wildcard("a/b/c", &[Range { start: 0, end: 3 }]),
+            None
+        );
+    }
+
     #[test]
     fn test_merge_ranges() {
+        let merge = |ranges: Vec<&str>| {
+            let tokens = ranges
+                .iter()
+                .map(|r| Pattern::new_unchecked(r.to_string()).segments[0][0].clone())
+                .collect::<Vec<_>>();
+            merge_ranges(tokens.iter().collect()).map(|t| t.to_string())
+        };
+        assert_eq!(
+            merge(vec!["[1-3]", "[4-6]", "[7-9]"]),
+            Some("[1-9]".to_string())
+        );
+        assert_eq!(merge(vec!["[1-3]", "[47]"]), Some("[1-47]".to_string()));
+        assert_eq!(merge(vec!["[2-5]", "[14]"]), Some("[1-5]".to_string()));
+        // a negated class matches the characters not listed
+        assert_eq!(merge(vec!["[!ab]", "[cd]"]), None);
+    }
+
+    #[test]
+    fn test_merge_classes() {
+        let merge = |lines: Vec<&str>| {
+            let patterns = lines
+                .iter()
+                .map(|l| Pattern::new_unchecked(l.to_string()))
+                .collect::<Vec<_>>();
+            merge_classes(&patterns).map(|p| p.to_string())
+        };
         assert_eq!(
-            merge_ranges(vec!["[1-3]", "[4-6]", "[7-9]"]),
-            "[1-9]".to_string()
+            merge(vec!["a/[b-c]", "a/[df]"]),
+            Some("a/[b-df]".to_string())
         );
-        assert_eq!(merge_ranges(vec!["[1-3]", "[47]"]), "[1-47]".to_string());
-        assert_eq!(merge_ranges(vec!["[2-5]", "[14]"]), "[1-5]".to_string());
+        assert_eq!(merge(vec!["a/[b-c]", "b/[df]"]), None);
+        assert_eq!(merge(vec!["a/[!b-c]", "a/[df]"]), None);
     }
 }
@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    file::Content,
+    matcher::Matcher,
+    pattern::{Pattern, Token},
+    printv,
+    walk::Walker,
+};
+
+use super::{lint::literal_parents, refactor::Refactor};
+
+// the path matched by a negation written literally (e.g. `!a/b` -> a/b)
+// a single name (e.g. `!b`) matches at any depth, so it is not literal
+fn literal_path(pattern: &Pattern) -> Option<PathBuf> {
+    if !pattern.anchored && pattern.segments.len() == 1 {
+        return None;
+    }
+    pattern
+        .segments
+        .iter()
+        .map(|segment| {
+            segment
+                .iter()
+                .map(|token| match token {
+                    Token::Char(c) | Token::Escaped(c) => Some(*c),
+                    _ => None,
+                })
+                .collect::<Option<String>>()
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|segments| PathBuf::from(segments.join("/")))
+}
+
+impl Refactor {
+    // remove negations re-including nothing ignored by the lines before them,
+    // and report the ones under an excluded directory (git does not look into it)
+    pub fn negation(&mut self) -> &mut Self {
+        let (verbose, root, tree, file) = self.get_borrows();
+        if verbose == 2 {
+            printv!(root, tree, file);
+        }
+
+        let line_num = file.content.len();
+        let (lines, protected): (Vec<_>, Vec<_>) = file
+            .content
+            .iter()
+            .filter_map(|line| match &line.content {
+                Content::Pattern(p) => Some((p.clone(), file.is_protected(line))),
+                _ => None,
+            })
+            .unzip();
+        let patterns = lines
+            .iter()
+            .map(|line| Pattern::new_unchecked(line.clone()))
+            .collect::<Vec<_>>();
+        let matcher = Matcher::new(&patterns);
+        // whether the lines before the k-th one ignore the path
+        let ignored_before = |path: &Path, is_dir: bool, k: usize| {
+            matcher
+                .matches(path, is_dir)
+                .into_iter()
+                .rev()
+                .find(|i| *i < k)
+                .is_some_and(|i| !patterns[i].negated)
+        };
+        let paths = if self.strict() {
+            Vec::new()
+        } else {
+            Walker::new(&root, |dir| matcher.is_ignored(dir, true)).collect::<Vec<_>>()
+        };
+
+        let mut useless = Vec::new();
+        let mut ineffective = Vec::new();
+        for (k, (line, pattern)) in lines.iter().zip(patterns.iter()).enumerate() {
+            if !pattern.negated {
+                continue;
+            }
+            if let Some(parent) = literal_parents(pattern)
+                .into_iter()
+                .find(|parent| matcher.is_ignored(Path::new(parent), true))
+            {
+                ineffective.push((line.clone(), parent));
+                continue;
+            }
+            // a literal path is checked whether it exists or not,
+            // other negations only against the tree (never in strict mode)
+            let re_includes = match literal_path(pattern) {
+                Some(path) => [true, false]
+                    .into_iter()
+                    .filter(|is_dir| *is_dir || !pattern.dir_only)
+                    .any(|is_dir| ignored_before(&path, is_dir, k)),
+                None => {
+                    self.strict()
+                        || paths.iter().any(|(path, is_dir)| {
+                            matcher.matches(path, *is_dir).contains(&k)
+                                && ignored_before(path, *is_dir, k)
+                        })
+                }
+            };
+            if !re_includes && !protected[k] {
+                useless.push(line.clone());
+            }
+        }
+
+        for line in useless {
+            self.file_mut().remove_line(line, verbose);
+        }
+        let warnings = ineffective
+            .into_iter()
+            .map(|(line, parent)| {
+                format!(
+                    "Ineffective negation: {} (the parent directory {}/ is excluded)",
+                    line, parent
+                )
+            })
+            .collect::<Vec<_>>();
+        if verbose > 0 {
+            for warning in warnings.iter() {
+                println!("{}\r\n", warning);
+            }
+        }
+        self.write_report(warnings);
+        self.finish(false, "negation", line_num);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process::test, show_result};
+    #[test]
+    fn test_negation() {
+        for level in 1..=1 {
+            for path in test::get_input_paths("negation") {
+                test::show_title(&path, level);
+                let refactor = &mut Refactor::new(&path, level, 2);
+                let result = refactor.preprocess().negation().postprocess();
+                show_result!(&result.file());
+                assert!(test::file_cmp(
+                    result.file(),
+                    test::get_expected_path(&path, level)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_literal_path() {
+        let cases = vec![
+            ("!/a", Some("a")),
+            ("!a/b/", Some("a/b")),
+            ("!a/\\*", Some("a/*")),
+            ("!a", None),
+            ("!a/*.txt", None),
+        ];
+        for (pat, expected) in cases {
+            assert_eq!(
+                literal_path(&Pattern::new_unchecked(pat.to_string())),
+                expected.map(PathBuf::from),
+                "Failed: {:?}",
+                pat
+            );
+        }
+    }
+}
@@ -9,10 +9,12 @@ impl Refactor {
         }
         let line_num = self.file().content.len();
         self.file_mut().remove_dupl();
+        let dec = line_num - self.file().content.len();
         self.write_report(vec![format!(
             "Lines reduced by postprocess process: {}",
-            line_num - self.file().content.len()
+            dec
         )]);
+        self.record_reduction("postprocess", dec, false);
         if let Some(pended) = self.pended() {
             self.state = pended;
         }
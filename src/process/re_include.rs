@@ -1,180 +1,271 @@
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
-    fs,
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
 };
 
-use fs_tree::FsTree;
-
 use super::refactor::Refactor;
 
-use crate::pattern::{does_match, ToString};
+use crate::file::Content;
+use crate::matcher::Matcher;
+use crate::pattern::{Kind, Pattern, ToString, Token};
 #[allow(unused_imports)]
-use crate::{
-    pattern::{expand_range, Kind},
-    printv,
-};
+use crate::printv;
+use crate::tree::DirectoryTree;
+use crate::walk::{is_dir, is_file, read_children, walk_dirs};
 
-#[allow(unused_variables)]
-fn get_children(
-    globals: HashMap<String, Kind>,
-    re_included: HashMap<String, Kind>,
-    root_path: PathBuf,
-    parent_path: PathBuf,
-    gign_path: PathBuf,
-) -> Vec<PathBuf> {
-    // get children (except globally ignored ones)
-    if let Ok(rd) = fs::read_dir(
-        root_path.join(
-            parent_path
-                .clone()
-                .strip_prefix("/")
-                .unwrap_or(&parent_path),
-        ),
-    ) {
-        rd.filter_map(|entry| {
-            let path = entry.unwrap().path();
-            let path_file = path.file_name().unwrap();
-            let find_from_map = |map: HashMap<String, Kind>| -> bool {
-                map.iter()
-                    .find(|(ref s_raw, ref k)| {
-                        expand_range(s_raw.to_string())
-                            .into_iter()
-                            .find(|s| match k {
-                                // if path is global, compare with file name
-                                // if normal, compare with parent path + file name
-                                // if wildcard, compare with file name without the first character
-                                Kind::Global => *s == path_file.to_str().unwrap(),
-                                Kind::Normal => {
-                                    *s == parent_path
-                                        .join(
-                                            path_file
-                                                .to_str()
-                                                .unwrap()
-                                                .strip_prefix("/")
-                                                .unwrap_or(path_file.to_str().unwrap()),
-                                        )
-                                        .to_str()
-                                        .unwrap()
-                                }
-                                Kind::Wildcard => {
-                                    s.chars().skip(1).collect::<String>()
-                                        == path_file
-                                            .to_str()
-                                            .unwrap()
-                                            .chars()
-                                            .skip(1)
-                                            .collect::<String>()
-                                }
-                                _ => panic!("Invalid Kind"),
-                            })
-                            .is_some()
-                    })
-                    .is_some()
-            };
-            // if (!find_from_map(globals.clone())
-            //     || (find_from_map(globals.clone()) && find_from_map(re_included.clone())))
-            //     && path != gign_path
-            if !find_from_map(globals.clone())
-                || (find_from_map(globals.clone()) && find_from_map(re_included.clone()))
-            {
-                Some(path.strip_prefix(root_path.clone()).unwrap().to_path_buf())
-            } else {
-                None
-            }
-        })
-        .collect()
-    } else {
-        Vec::new()
+// the lines ignoring a name at any depth (e.g. `*.log`) and the ones re-including paths
+// (e.g. `!a/b`), which the lines of the tree do not account for
+struct Globals {
+    ignored: Matcher,
+    re_included: Matcher,
+}
+impl Globals {
+    fn new(tree: &DirectoryTree) -> Self {
+        let patterns = |map: &BTreeMap<String, Kind>, dir_only: &BTreeSet<String>| {
+            map.iter()
+                .map(|(path, kind)| {
+                    let pattern =
+                        Pattern::new_unchecked(path.clone()).with_dir_only(dir_only.contains(path));
+                    if *kind == Kind::Normal {
+                        pattern.anchor()
+                    } else {
+                        pattern
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        Self {
+            ignored: tree.globals_matcher(),
+            re_included: Matcher::new(&patterns(&tree.re_included, &tree.dir_only_re_included)),
+        }
+    }
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.ignored.is_match(path, is_dir) && !self.re_included.is_match(path, is_dir)
     }
 }
 
-#[allow(unused_variables)]
+// children of the directory (except globally ignored ones)
+fn get_children(globals: &Globals, root: &Path, parent_path: &Path) -> Vec<PathBuf> {
+    read_children(&root.join(parent_path))
+        .into_iter()
+        .map(|(name, is_dir)| (parent_path.join(name), is_dir))
+        .filter(|(path, is_dir)| !globals.is_ignored(path, *is_dir))
+        .map(|(path, _)| path)
+        .collect()
+}
+
+// lines directly under the directory (except globally ignored ones), and the children they ignore
 fn get_ign_children(
-    paths: Vec<&PathBuf>,
-    node_line_map_keys: Vec<&PathBuf>,
-    globals: HashMap<String, Kind>,
-    re_included: HashMap<String, Kind>,
-    parent_path: PathBuf,
-    gign_path: PathBuf,
-) -> (BTreeSet<PathBuf>, usize, BTreeSet<PathBuf>, usize) {
-    // get ignored children (except globally ignored ones, should be in node_line_map)
-    let find_from_map = |map: HashMap<String, Kind>, path_file: &str| -> bool {
-        map.iter()
-            .find(|(ref s_raw, ref k)| {
-                expand_range(s_raw.to_string())
-                    .into_iter()
-                    .find(|s| match k {
-                        // if path is global, compare with file name
-                        // if normal, compare with parent path + file name
-                        // if wildcard, compare with file name without the first character
-                        Kind::Global => *s == path_file,
-                        Kind::Normal => {
-                            *s == parent_path
-                                .join(path_file.strip_prefix("/").unwrap_or(path_file))
-                                .to_str()
-                                .unwrap()
-                        }
-                        Kind::Wildcard => s[1..] == path_file[1..],
-                        _ => panic!("Invalid Kind"),
-                    })
-                    .is_some()
-            })
-            .is_some()
-    };
-    let ign_children_lines = paths
+    tree: &DirectoryTree,
+    globals: &Globals,
+    root: &Path,
+    parent_path: &Path,
+    nodes: Vec<&PathBuf>,
+    children: &[PathBuf],
+) -> (BTreeSet<PathBuf>, BTreeSet<PathBuf>) {
+    // paths that do not exist count as directories
+    let is_dir = |path: &Path| !is_file(&root.join(path));
+    let ign_children_lines = nodes
         .into_iter()
-        .filter_map(|path| {
-            let path_file = path.file_name().unwrap().to_str().unwrap();
-            // if (!find_from_map(globals.clone(), path_file)
-            //     || (find_from_map(globals.clone(), path_file)
-            //         && find_from_map(re_included.clone(), path_file)))
-            //     && *path != gign_path
-            if !find_from_map(globals.clone(), path_file)
-                || (find_from_map(globals.clone(), path_file)
-                    && find_from_map(re_included.clone(), path_file))
-            {
-                Some(path)
-            } else {
-                None
-            }
-        })
-        .filter(|path| {
-            node_line_map_keys.contains(&&parent_path.join(path.strip_prefix("/").unwrap_or(path)))
-        })
-        .map(|key| parent_path.join(key))
+        .map(|key| parent_path.join(key.strip_prefix("/").unwrap_or(key)))
+        .filter(|path| !globals.is_ignored(path, is_dir(path)))
+        .filter(|path| tree.node_line_map.contains_key(path))
         .collect::<BTreeSet<PathBuf>>();
-    let ign_children = ign_children_lines
+    let matcher = Matcher::new(
+        &ign_children_lines
+            .iter()
+            .map(|line| {
+                Pattern::new_unchecked(line.to_string())
+                    .anchor()
+                    .with_dir_only(tree.is_dir_only(line))
+            })
+            .collect::<Vec<_>>(),
+    );
+    let ign_children = children
         .iter()
-        .flat_map(|path| expand_range(path.to_string()))
-        .map(|s| PathBuf::from(s))
-        .filter_map(|path| {
-            let path_file = path.file_name().unwrap().to_str().unwrap();
-            // if (!find_from_map(globals.clone(), path_file)
-            //     || (find_from_map(globals.clone(), path_file)
-            //         && find_from_map(re_included.clone(), path_file)))
-            //     && *path != gign_path
-            if !find_from_map(globals.clone(), path_file)
-                || (find_from_map(globals.clone(), path_file)
-                    && find_from_map(re_included.clone(), path_file))
-            {
-                Some(path)
-            } else {
-                None
-            }
-        })
+        .filter(|child| matcher.is_match(child, is_dir(child)))
+        .cloned()
         .collect::<BTreeSet<PathBuf>>();
-    let ign_children_num = ign_children.len();
-    let ign_children_lines_num = ign_children_lines.len();
-    (
+    (ign_children, ign_children_lines)
+}
+
+// children of a directory and the ones ignored by lines directly under it
+#[derive(Debug, Clone)]
+struct Level {
+    children: Vec<PathBuf>,
+    ign_children: BTreeSet<PathBuf>,
+    ign_children_lines: BTreeSet<PathBuf>,
+}
+
+// how the subtree of a directory is expressed
+#[derive(Debug, Clone, Default)]
+struct Plan {
+    lines: usize,   // minimal number of lines for the subtree
+    full: bool,     // every path in the subtree is ignored
+    collapse: bool, // ignore the directory with `dir/*` and re-include the rest
+}
+
+fn get_level(
+    tree: &DirectoryTree,
+    globals: &Globals,
+    root: &Path,
+    parent_path: &Path,
+) -> Option<Level> {
+    // parent nodes should not be ignored for re-including children
+    if tree.node_line_map.contains_key(parent_path) {
+        return None;
+    }
+    let parent = tree.root.get(parent_path)?;
+    let ign_children_map = parent.children()?;
+    // all children (except globally ignored ones)
+    let children = get_children(globals, root, parent_path);
+    // nodes (ignored children) matching at least one child in the tree
+    let nodes = ign_children_map.keys().collect::<Vec<_>>();
+    let matcher = Matcher::new(
+        &nodes
+            .iter()
+            .map(|path| {
+                let node = parent_path.join(path.strip_prefix("/").unwrap_or(path));
+                Pattern::new_unchecked(node.to_string())
+                    .anchor()
+                    .with_dir_only(tree.is_dir_only(&node))
+            })
+            .collect::<Vec<_>>(),
+    );
+    let matched = children
+        .iter()
+        .flat_map(|child| matcher.matches(child, !is_file(&root.join(child))))
+        .collect::<BTreeSet<_>>();
+    let retained_children = nodes
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| matched.contains(i))
+        .map(|(_, path)| path)
+        .collect::<Vec<&PathBuf>>();
+    // ignored children (should be in node_line_map)
+    let (ign_children, ign_children_lines) = get_ign_children(
+        tree,
+        globals,
+        root,
+        parent_path,
+        retained_children,
+        &children,
+    );
+    Some(Level {
+        children,
         ign_children,
-        ign_children_num,
         ign_children_lines,
-        ign_children_lines_num,
-    )
+    })
+}
+
+// choose, for each directory, whether to collapse it into `dir/*` + re-inclusions
+// a child whose whole subtree is ignored costs nothing when one of its ancestors is collapsed,
+// so deep structures can be hoisted to the ancestor level with the fewest lines
+fn get_plan(
+    levels: &BTreeMap<PathBuf, Level>,
+    plans: &mut BTreeMap<PathBuf, Plan>,
+    root: &Path,
+    parent_path: &Path,
+) -> Plan {
+    let Some(level) = levels.get(parent_path) else {
+        return Plan::default();
+    };
+    let mut explicit = level.ign_children_lines.len();
+    let mut collapse = 1;
+    let mut full = !level.children.is_empty();
+    for child in level.children.iter() {
+        if level.ign_children.contains(child) {
+            continue;
+        }
+        let child_plan = if is_dir(&root.join(child)) {
+            get_plan(levels, plans, root, child)
+        } else {
+            Plan::default()
+        };
+        explicit += child_plan.lines;
+        if !child_plan.full {
+            collapse += 1 + child_plan.lines;
+            full = false;
+        }
+    }
+    let plan = Plan {
+        lines: explicit.min(collapse),
+        full,
+        collapse: collapse < explicit,
+    };
+    plans.insert(parent_path.to_path_buf(), plan.clone());
+    plan
+}
+
+// the keys of the node-line map are lines, so they are not escaped like the paths in the tree
+fn line_path(line: &Path) -> PathBuf {
+    let segments = line
+        .iter()
+        .filter(|s| *s != "/")
+        .map(|s| s.to_string_lossy())
+        .collect::<Vec<_>>();
+    if segments.len() > 1 {
+        PathBuf::from(segments.join("/"))
+    } else {
+        PathBuf::from(format!("/{}", segments.join("/")))
+    }
 }
 
 impl Refactor {
+    fn apply_plan(
+        &mut self,
+        levels: &BTreeMap<PathBuf, Level>,
+        plans: &BTreeMap<PathBuf, Plan>,
+        parent_path: &Path,
+    ) {
+        let (verbose, root, tree, _) = self.get_borrows();
+        let (Some(level), Some(plan)) = (levels.get(parent_path), plans.get(parent_path)) else {
+            return;
+        };
+        let not_ign_children = level
+            .children
+            .iter()
+            .filter(|child| !level.ign_children.contains(*child))
+            .map(|child| (child, plans.get(child).cloned().unwrap_or_default()));
+        if !plan.collapse {
+            for (child, _) in not_ign_children {
+                if is_dir(&root.join(child)) {
+                    self.apply_plan(levels, plans, child);
+                }
+            }
+            return;
+        }
+        if verbose == 2 {
+            printv!(parent_path, level, plan);
+        }
+        let file = self.file_mut();
+        // remove lines
+        for child_path in level.ign_children_lines.iter() {
+            file.remove_line_with_path(line_path(child_path), verbose);
+        }
+        for (child, _) in not_ign_children.clone().filter(|(_, p)| p.full) {
+            for child_path in tree.node_line_map.keys().filter(|k| k.starts_with(child)) {
+                file.remove_line_with_path(line_path(child_path), verbose);
+            }
+        }
+        // ignore parent
+        file.add_line(
+            Pattern::from_path(parent_path)
+                .join(vec![Token::Wildcard])
+                .anchor_top_level()
+                .to_string(),
+            verbose,
+        );
+        // re-include child(ren) not ignored
+        for (child, _) in not_ign_children.filter(|(_, p)| !p.full) {
+            let new_line = Pattern::from_path(child).anchor_top_level().negate();
+            self.file_mut().add_line(new_line.to_string(), verbose);
+            if is_dir(&root.join(child)) {
+                self.apply_plan(levels, plans, child);
+            }
+        }
+    }
+
     pub fn re_include(&mut self) -> &mut Self {
         let (verbose, root, tree, file) = self.get_borrows();
         if verbose == 2 {
@@ -182,101 +273,38 @@ impl Refactor {
         }
 
         let line_num = file.content.len();
-        // iterate over nodes (parent nodes)
-        // parent nodes should not be ignored for re-including children
-        if let Ok(parent_tree) = FsTree::read_at(&root) {
-            for parent_path in parent_tree.paths() {
-                if let Some(parent) = tree.root.get(parent_path.clone()) {
-                    // check if parent is not ignored
-                    if tree.node_line_map.get(&parent_path).is_none() {
-                        if let Some(ign_children_map) = parent.children().as_mut() {
-                            // println!("{:?}", ign_children_map);
-                            // all children (except globally ignored ones)
-                            let children = get_children(
-                                tree.globals.clone(),
-                                tree.re_included.clone(),
-                                root.clone(),
-                                parent_path.clone(),
-                                file.path.clone(),
-                            );
-                            let children_num = children.len();
-                            let retained_children: BTreeMap<_, _> = ign_children_map
-                                .iter()
-                                .filter(|(path, _)| {
-                                    children.iter().any(|child| {
-                                        does_match(
-                                            child,
-                                            &parent_path
-                                                .join(path.strip_prefix("/").unwrap_or(path))
-                                                .to_string(),
-                                        )
-                                    })
-                                })
-                                .map(|(path, value)| (path.clone(), value.clone()))
-                                .collect();
-                            *ign_children_map = &retained_children;
-                            // println!("{:?}", ign_children_map);
-                            // ignored children (should be in node_line_map)
-                            let (
-                                ign_children,
-                                ign_children_num,
-                                ign_children_lines,
-                                ign_children_lines_num,
-                            ) = get_ign_children(
-                                ign_children_map.keys().collect::<Vec<&PathBuf>>(),
-                                tree.node_line_map.keys().collect::<Vec<&PathBuf>>(),
-                                tree.globals.clone(),
-                                tree.re_included.clone(),
-                                parent_path.clone(),
-                                file.path.clone(),
-                            );
-                            if verbose == 2 {
-                                printv!(parent_path, ign_children, ign_children_lines, children);
-                            }
-                            if 1 + children_num - ign_children_num < ign_children_lines_num {
-                                let file = self.file_mut();
-                                // remove lines
-                                for child_path in ign_children_lines.clone().into_iter() {
-                                    println!("Removing: {:?}", child_path);
-                                    file.remove_line_with_path(
-                                        if child_path.to_str().unwrap().contains("/") {
-                                            child_path
-                                        } else {
-                                            PathBuf::from(format!(
-                                                "/{}",
-                                                child_path.to_str().unwrap()
-                                            ))
-                                        },
-                                        verbose,
-                                    );
-                                }
-                                // ignore parent
-                                file.add_line(
-                                    if parent_path.as_os_str() == "" {
-                                        String::from("/*")
-                                    } else {
-                                        parent_path.join("*").to_string()
-                                    },
-                                    verbose,
-                                );
-                                // re-include child(ren) not ignored
-                                for child_path in children {
-                                    if !ign_children.contains(&child_path) {
-                                        let new_line = format!("!{}", child_path.to_str().unwrap());
-                                        file.add_line(new_line, verbose);
-                                    }
-                                }
-                                // self.halt();
-                            }
-                        }
-                    }
-                }
-            }
-        } else {
-            println!("{:?}", &root);
-            eprintln!("Failed to read directory tree. Aborting.");
-            std::process::exit(1);
+        // `dir/*` with re-inclusions only holds for the children present in the tree
+        if self.strict() {
+            self.finish(true, "re_include", line_num);
+            return self;
         }
+        // iterate over nodes (parent nodes)
+        // directories outside of the tree of lines or already ignored as a whole cannot be
+        // collapsed, so they are not walked into (e.g. `.git`, `node_modules` or `target`)
+        let matcher = Matcher::new(
+            &file
+                .content
+                .iter()
+                .filter_map(|line| match &line.content {
+                    Content::Pattern(p) => Some(Pattern::new_unchecked(p.to_string())),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        );
+        let globals = Globals::new(&tree);
+        let levels = walk_dirs(&root, |dir| {
+            tree.root.get(dir).is_none()
+                || tree.node_line_map.contains_key(dir)
+                || matcher.is_ignored(dir, true)
+        })
+        .into_iter()
+        .filter_map(|parent_path| {
+            get_level(&tree, &globals, &root, &parent_path).map(|level| (parent_path, level))
+        })
+        .collect::<BTreeMap<_, _>>();
+        let mut plans = BTreeMap::new();
+        get_plan(&levels, &mut plans, &root, Path::new(""));
+        self.apply_plan(&levels, &plans, Path::new(""));
         self.finish(true, "re_include", line_num);
         self
     }
@@ -1,8 +1,13 @@
 use crate::core::{file::File, tree::DirectoryTree};
-// use crate::parse::parse;
+use crate::matcher::Matcher;
+use crate::parse;
+use crate::process::merge::{Generalization, MAX_COMBINATIONS};
+use crate::walk;
+use std::any::Any;
 use std::cell::{Ref, RefCell};
 use std::fs;
-use std::io::Write;
+use std::io::{self, Write};
+use std::panic;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
@@ -12,7 +17,12 @@ pub struct State {
     file: RefCell<File>,
     pub root: PathBuf,
     pub level: u8,
+    pub strict: bool, // only apply transformations equivalent for every possible tree
+    pub prune_dead: bool, // remove lines matching no path
+    pub history: Option<Vec<PathBuf>>, // paths ever seen in the repository (relative to the root)
+    pub max_combinations: usize, // sets of lines merge tries at most for each size
     pub tree: DirectoryTree,
+    pub generalizations: Vec<Generalization>,
     pub prev: Option<Box<State>>,
 }
 
@@ -30,13 +40,21 @@ impl State {
     pub fn new(path: &Path, level: u8, verbose: u8) -> Self {
         let mut root = path.to_path_buf();
         root.pop();
+        Self::from_file(File::new(path.to_path_buf()), root, level, verbose)
+    }
+    pub fn from_file(file: File, root: PathBuf, level: u8, verbose: u8) -> Self {
         State {
             verbose,
-            orig_file: File::new(path.to_path_buf()),
-            file: RefCell::new(File::new(path.to_path_buf())),
+            orig_file: file.clone(),
+            file: RefCell::new(file),
             root,
             level,
+            strict: false,
+            prune_dead: false,
+            history: None,
+            max_combinations: MAX_COMBINATIONS,
             tree: DirectoryTree::new(),
+            generalizations: Vec::new(),
             prev: None,
         }
     }
@@ -45,11 +63,22 @@ impl State {
     }
 }
 
+// settings of a run, other than the level and the verbosity
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub strict: bool,
+    pub prune_dead: bool,
+    pub history: Option<Vec<PathBuf>>,
+    pub protected: Vec<String>, // lines to leave untouched
+    pub max_combinations: Option<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Refactor {
     pub state: State,
     pub pended: Option<State>,
     report: Vec<String>,
+    reductions: Vec<(String, usize, bool)>, // (process, reduced lines, skipped)
 }
 impl Refactor {
     pub fn new(path: &Path, level: u8, verbose: u8) -> Self {
@@ -57,6 +86,15 @@ impl Refactor {
             state: State::new(path, level, verbose),
             pended: None,
             report: Vec::new(),
+            reductions: Vec::new(),
+        }
+    }
+    pub fn from_file(file: File, root: PathBuf, level: u8, verbose: u8) -> Self {
+        Refactor {
+            state: State::from_file(file, root, level, verbose),
+            pended: None,
+            report: Vec::new(),
+            reductions: Vec::new(),
         }
     }
     pub fn verbose(&self) -> u8 {
@@ -65,6 +103,36 @@ impl Refactor {
     pub fn level(&self) -> u8 {
         self.state.level
     }
+    pub fn strict(&self) -> bool {
+        self.state.strict
+    }
+    pub fn set_strict(&mut self, strict: bool) -> &mut Self {
+        self.state.strict = strict;
+        self
+    }
+    pub fn set_prune_dead(&mut self, prune_dead: bool) -> &mut Self {
+        self.state.prune_dead = prune_dead;
+        self
+    }
+    pub fn set_history(&mut self, history: Option<Vec<PathBuf>>) -> &mut Self {
+        self.state.history = history;
+        self
+    }
+    pub fn set_max_combinations(&mut self, max_combinations: usize) -> &mut Self {
+        self.state.max_combinations = max_combinations;
+        self
+    }
+    pub fn protect(&mut self, lines: &[String]) -> &mut Self {
+        self.file_mut().protect(lines);
+        self
+    }
+    pub fn configure(&mut self, options: &Options) -> &mut Self {
+        self.set_strict(options.strict)
+            .set_prune_dead(options.prune_dead)
+            .set_history(options.history.clone())
+            .set_max_combinations(options.max_combinations.unwrap_or(MAX_COMBINATIONS))
+            .protect(&options.protected)
+    }
     pub fn orig_file(&self) -> &File {
         &self.state.orig_file
     }
@@ -89,10 +157,23 @@ impl Refactor {
     pub fn rebuild_tree(&mut self) {
         self.state.tree = DirectoryTree::build_tree_from_file(&self.file());
     }
+    pub fn generalizations(&self) -> &Vec<Generalization> {
+        &self.state.generalizations
+    }
     pub fn pended(&self) -> Option<State> {
         self.pended.clone()
     }
+    pub fn reductions(&self) -> &Vec<(String, usize, bool)> {
+        &self.reductions
+    }
+    pub fn record_reduction(&mut self, process: &str, reduced: usize, skipped: bool) {
+        self.reductions
+            .push((process.to_string(), reduced, skipped));
+    }
     pub fn skip_report(&mut self) {
+        if let Some(last) = self.reductions.last_mut() {
+            last.2 = last.1 > 0;
+        }
         let last = self.report.pop().unwrap();
         self.report.push(format!(
             "{}{}",
@@ -145,11 +226,12 @@ impl Refactor {
                 if pended.lines_diff() > 0 {
                     self.skip_report();
                 }
+                let dec = line_num - self.file().content.len();
                 self.write_report(vec![format!(
                     "Lines reduced by {} process: {}",
-                    process,
-                    line_num - self.file().content.len()
+                    process, dec
                 )]);
+                self.record_reduction(process, dec, false);
                 self.update(violate);
             } else {
                 let dec = line_num - self.file().content.len();
@@ -159,88 +241,160 @@ impl Refactor {
                     dec,
                     if dec > 0 { " (Skipped)" } else { "" }
                 )]);
+                self.record_reduction(process, dec, dec > 0);
                 self.back();
             }
         } else {
+            let dec = line_num - self.file().content.len();
             self.write_report(vec![format!(
                 "Lines reduced by {} process: {}",
-                process,
-                line_num - self.file().content.len()
+                process, dec
             )]);
+            self.record_reduction(process, dec, false);
             self.update(violate);
         }
         if !violate {
             self.state.prev = Some(Box::new(self.state.clone()));
         }
     }
+    // whether the path (relative to the root) is a file in the real tree
+    // directory-only lines (e.g. `logs/`) never apply to files
+    pub fn is_file(&self, path: &Path) -> bool {
+        walk::is_file(&self.root().join(path.strip_prefix("/").unwrap_or(path)))
+    }
     pub fn is_normally_ignored(&self, path: &Path) -> bool {
         self.tree().node_line_map.get(path).is_some()
+            && !(self.tree().is_dir_only(path) && self.is_file(path))
     }
-    pub fn is_globally_ignored(&self, path: &Path) -> bool {
-        // println!("path: {:?}", path);
-        let file_name = path.file_name().unwrap().to_str().unwrap();
-        self.tree()
-            .globals
-            .keys()
-            .find(|&x| x == file_name || (&x[..1] == "*" && x[1..] == file_name[1..]))
-            .is_some()
+    // `globals` is the matcher of the global lines (`DirectoryTree::globals_matcher`)
+    pub fn is_globally_ignored(&self, globals: &Matcher, path: &Path) -> bool {
+        globals.is_match(path, !self.is_file(path))
     }
-    pub fn is_ignored(&self, path: &Path) -> bool {
-        self.is_normally_ignored(path) || self.is_globally_ignored(path)
+    pub fn is_ignored(&self, globals: &Matcher, path: &Path) -> bool {
+        self.is_normally_ignored(path) || self.is_globally_ignored(globals, path)
     }
-    fn run_inner(path: &Path, level: u8, verbose: u8) -> Refactor {
+    fn run_inner(path: &Path, level: u8, verbose: u8, options: &Options) -> Refactor {
         let refactor = &mut Refactor::new(path, level, verbose);
         refactor
+            .configure(options)
             .preprocess()
             .containment()
+            .dead()
+            .negation()
             .re_include()
             .merge()
             .postprocess()
             .clone()
     }
     pub fn run(path: &Path, level: u8) -> Refactor {
-        Self::run_inner(path, level, 0)
+        Self::run_inner(path, level, 0, &Options::default())
     }
     pub fn run_verbose(path: &Path, level: u8, verbose: u8) -> Refactor {
-        Self::run_inner(path, level, verbose)
+        Self::run_inner(path, level, verbose, &Options::default())
+    }
+    // run without any transformation that depends on the files present in the tree
+    pub fn run_strict(path: &Path, level: u8, verbose: u8) -> Refactor {
+        let options = Options {
+            strict: true,
+            ..Options::default()
+        };
+        Self::run_inner(path, level, verbose, &options)
+    }
+    pub fn run_with(path: &Path, level: u8, verbose: u8, options: &Options) -> Refactor {
+        Self::run_inner(path, level, verbose, options)
+    }
+    // whether the file can be read and every line of it is valid
+    pub fn check(path: &Path) -> Result<(), String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        match parse::validate(&content)
+            .into_iter()
+            .find(|d| d.severity == parse::Severity::Error)
+        {
+            Some(diagnostic) => Err(format!("Invalid pattern found: {}", diagnostic)),
+            None => Ok(()),
+        }
+    }
+    // same as run, but reports failures as errors instead of aborting the process
+    pub fn try_run(path: &Path, level: u8, options: &Options) -> Result<Refactor, String> {
+        Self::check(path)?;
+        panic::catch_unwind(|| Self::run_inner(path, level, 0, options)).map_err(panic_message)
+    }
+    pub fn try_save(&self, path: &Path) -> io::Result<()> {
+        write_atomic(path, &self.file().to_text())
     }
     pub fn save(&self, path: PathBuf) {
-        let f = fs::File::create(path.clone()).expect(&format!(
-            "Failed to save result to: {}",
-            path.clone().display()
-        ));
-        let mut file = std::io::BufWriter::new(f);
-        for line in self.file().content.iter() {
-            if let Err(_) = writeln!(file, "{}", line.content.unwrap()) {
-                fs::remove_file(path.clone()).expect(&format!(
-                    "Failed to remove file: {}",
-                    path.clone().display()
-                ));
-                eprintln!("Error occurred when writing to file: {}", path.display());
-                std::process::exit(1);
-            }
+        if let Err(e) = self.try_save(&path) {
+            eprintln!(
+                "Error occurred when writing to file: {} ({})",
+                path.display(),
+                e
+            );
+            std::process::exit(1);
         }
     }
     pub fn save_orig(&self, path: &Path) {
-        let f =
-            fs::File::create(path).expect(&format!("Failed to save result to: {}", path.display()));
-        let mut file = std::io::BufWriter::new(f);
-        for line in self.orig_file().content.iter() {
-            if let Err(_) = writeln!(file, "{}", line.content.unwrap()) {
-                fs::remove_file(path).expect(&format!("Failed to remove file: {}", path.display()));
-                eprintln!("Error occurred when writing to file: {}", path.display());
-                std::process::exit(1);
-            }
+        if let Err(e) = write_atomic(path, &self.orig_file().to_text()) {
+            eprintln!(
+                "Error occurred when writing to file: {} ({})",
+                path.display(),
+                e
+            );
+            std::process::exit(1);
         }
     }
+    // copy the file to `<path>.bak` before it is overwritten
+    pub fn backup(path: &Path) -> io::Result<PathBuf> {
+        let mut backup_path = path.as_os_str().to_os_string();
+        backup_path.push(".bak");
+        let backup_path = PathBuf::from(backup_path);
+        fs::copy(path, &backup_path)?;
+        Ok(backup_path)
+    }
+    // merges that may ignore files created later, for reviewers to judge
+    pub fn generalization_report(&self) -> Vec<String> {
+        let generalizations = self.generalizations();
+        if generalizations.is_empty() {
+            return Vec::new();
+        }
+        let mut lines = vec![
+            "==================".to_string(),
+            format!(
+                "Generalizations (may ignore files created later): {}",
+                generalizations.len()
+            ),
+        ];
+        for g in generalizations.iter() {
+            lines.push(format!("{} <- {}", g.line, g.merged.join(", ")));
+            lines.push(format!(
+                "    Checked against: {}",
+                if g.checked.is_empty() {
+                    "(none)".to_string()
+                } else {
+                    // the whole tree may have been checked
+                    let shown = g
+                        .checked
+                        .iter()
+                        .take(10)
+                        .map(|p| p.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    match g.checked.len().saturating_sub(10) {
+                        0 => shown,
+                        more => format!("{} ({} more)", shown, more),
+                    }
+                }
+            ));
+            lines.push(format!("    Also matches, e.g.: {}", g.examples.join(", ")));
+        }
+        lines
+    }
     pub fn save_report(&self, path: &Path, result_path: PathBuf) {
-        let f =
-            fs::File::create(path).expect(&format!("Failed to save result to: {}", path.display()));
-        let mut file = std::io::BufWriter::new(f);
         let report_content = [
             "Refactorign Report".to_string(),
             "==================".to_string(),
             format!("Refactoring level: {}", self.level()),
+            format!("Strict mode: {}", if self.strict() { "on" } else { "off" }),
             format!("Original file: {}", self.orig_file().path.display()),
             format!("Refactored file: {}", result_path.display()),
             "==================".to_string(),
@@ -252,12 +406,126 @@ impl Refactor {
             ),
             "==================".to_string(),
         ];
-        for line in report_content.iter().chain(self.report.iter()) {
-            if let Err(_) = writeln!(file, "{}", line) {
-                fs::remove_file(path).expect(&format!("Failed to remove file: {}", path.display()));
-                eprintln!("Error occurred when writing to file: {}", path.display());
-                std::process::exit(1);
+        let text = report_content
+            .iter()
+            .chain(self.report.iter())
+            .chain(self.generalization_report().iter())
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        if let Err(e) = write_atomic(path, &text) {
+            eprintln!(
+                "Error occurred when writing to file: {} ({})",
+                path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// write to a temporary file next to the destination and rename it over the destination,
+// so that the destination is never left truncated
+fn write_atomic(path: &Path, text: &str) -> io::Result<()> {
+    let tmp_path = path.with_file_name(format!(
+        ".{}.refactorign.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let result = (|| {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&tmp_path, metadata.permissions())?;
+        }
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn panic_message(e: Box<dyn Any + Send>) -> String {
+    if let Some(s) = e.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = e.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Unknown error".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process::test, show_result};
+    #[test]
+    fn test_run_strict() {
+        for level in 1..=1 {
+            for path in test::get_input_paths("strict") {
+                test::show_title(&path, level);
+                let result = Refactor::run_strict(&path, level, 2);
+                show_result!(&result.file());
+                assert!(test::file_cmp(
+                    result.file(),
+                    test::get_expected_path(&path, level)
+                ));
+            }
+        }
+    }
+
+    // lines marked by pragmas are neither removed, rewritten nor merged
+    #[test]
+    fn test_run_protected() {
+        for level in 1..=1 {
+            for path in test::get_input_paths("protected") {
+                test::show_title(&path, level);
+                let result = Refactor::run_verbose(&path, level, 2);
+                show_result!(&result.file());
+                assert!(test::file_cmp(
+                    result.file(),
+                    test::get_expected_path(&path, level)
+                ));
             }
         }
     }
+
+    #[test]
+    fn test_deterministic() {
+        for process in ["integration", "merge", "re_include", "strict"] {
+            for path in test::get_input_paths(process) {
+                let options = Options {
+                    strict: process == "strict",
+                    ..Options::default()
+                };
+                let first = Refactor::run_inner(&path, 2, 0, &options).file().to_text();
+                for _ in 0..10 {
+                    let result = Refactor::run_inner(&path, 2, 0, &options).file().to_text();
+                    assert_eq!(result, first, "Failed: {:?}", path);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = std::env::temp_dir().join(format!("refactorign_save_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".gitignore");
+        for text in ["a\r\n/a\r\nb\r\n", "a\n/a\nb", "# comment\n\na\n/a\n"] {
+            fs::write(&path, text).unwrap();
+            let refactor = Refactor::new(&path, 1, 0);
+            let backup_path = Refactor::backup(&path).unwrap();
+            refactor.save(path.clone());
+            assert_eq!(fs::read_to_string(&path).unwrap(), text);
+            assert_eq!(fs::read_to_string(&backup_path).unwrap(), text);
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        }
+        // a failed write leaves the destination untouched and no temporary file behind
+        fs::create_dir(dir.join("locked")).unwrap();
+        assert!(write_atomic(&dir.join("locked"), "a\n").is_err());
+        assert!(dir.join("locked").is_dir());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }